@@ -0,0 +1,35 @@
+use netspatch::{client::Client, job::{JobManager, JobResult}, server::Server, sink::StdoutSink};
+use std::{sync::{Arc,Mutex}, thread::sleep, time::Duration};
+
+fn main() {
+    let host = "localhost".to_string();
+    let port = 7878;
+
+    let stack = Arc::new(
+        Mutex::new(JobManager::new(&vec![4,4]).expect("Could not create stack")
+    ));
+
+    // Create the server
+    print!("Attempting to start server... ");
+    let server = Server::start(&host, port, stack, Duration::new(2, 0), Box::new(StdoutSink)).expect("Could not start server");
+    println!("Server started");
+
+    // Create the client
+    let mut client = Client::new(host, port);
+
+    // Lease five jobs at a time and answer them in one request
+    while client.query_batch(5).success() {
+        let mut results = Vec::new();
+        for job in &client.jobs {
+            results.push(JobResult::new(job, format!("Client says \"Hello World\" in response to job {}", job.to_uri()).into_bytes()));
+        }
+        client.respond_batch(results).expect("Error when sending batch response");
+    }
+
+    print!("Waiting for server to shut down automatically... ");
+
+    while server.is_running() {
+        sleep(Duration::new(0, 1000000));
+    }
+    println!("Server stopped");
+}
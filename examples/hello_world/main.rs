@@ -1,4 +1,4 @@
-use netspatch::{client::{Client, GetJobResult}, job::JobManager, server::Server};
+use netspatch::{client::{Client, GetJobResult}, job::JobManager, server::Server, sink::StdoutSink};
 use std::{sync::{Arc,Mutex}, thread::sleep, time::Duration};
 
 fn main() {
@@ -11,7 +11,7 @@ fn main() {
 
     // Create the server
     print!("Attempting to start server... ");
-    let server = Server::start(&host, port, stack, Duration::new(2, 0)).expect("Could not start server");
+    let server = Server::start(&host, port, stack, Duration::new(2, 0), Box::new(StdoutSink)).expect("Could not start server");
     println!("Server started");
 
     // Wait
@@ -24,12 +24,17 @@ fn main() {
             GetJobResult::JobLoaded => {
                 let job = client.job.clone().unwrap();
                 println!("Client: Loaded job with URI {}", job.to_uri());
-                client.respond(format!("Client says \"Hello World\" in response to job {}", job.to_uri()));
+                client.respond(format!("Client says \"Hello World\" in response to job {}", job.to_uri())).expect("Error when sending job response");
             }
             GetJobResult::NoJobsLeft => {
                 println!("Client: No jobs left");
                 break;
             }
+            GetJobResult::Wait(duration) => {
+                println!("Client: Waiting on jobs held by other clients");
+                sleep(duration);
+                continue;
+            }
             GetJobResult::Error => {
                 println!("Client: Error encountered");
                 break;
@@ -1,4 +1,4 @@
-use netspatch::{client::Client, job::JobManager, server::Server};
+use netspatch::{client::Client, job::JobManager, server::Server, sink::StdoutSink};
 use std::{sync::{Arc,Mutex}, thread::sleep, time::Duration};
 
 fn main() {
@@ -11,7 +11,7 @@ fn main() {
 
     // Create the server
     print!("Attempting to start server... ");
-    let server = Server::start(&host, port, stack, Duration::new(2, 0)).expect("Could not start server");
+    let server = Server::start(&host, port, stack, Duration::new(2, 0), Box::new(StdoutSink)).expect("Could not start server");
     println!("Server started");
 
     // Create the client
@@ -19,7 +19,7 @@ fn main() {
 
     // Loop through the jobs
     while client.query().success() {
-        client.respond(format!("Client says \"Hello World\" in response to job {}", client.job.clone().unwrap().to_uri()));
+        client.respond(format!("Client says \"Hello World\" in response to job {}", client.job.clone().unwrap().to_uri())).expect("Error when sending job response");
     }
 
     print!("Waiting for server to shut down automatically... ");
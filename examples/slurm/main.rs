@@ -8,6 +8,7 @@ fn main() {
     let mut id = std::process::id().to_string();
     let mut timeout = Duration::new(1, 0);
     let mut retries: u64 = 0;
+    let mut heartbeat: Option<Duration> = None;
 
     let mut args: Vec<String> = env::args().collect();
     args.remove(0);
@@ -59,23 +60,45 @@ fn main() {
                 retries = retries_str.parse::<u64>().expect("Could not parse retry count");
                 args.remove(0);
             }
+            "--heartbeat" => {
+                args.remove(0);
+                let heartbeat_str = args.first().unwrap().to_string();
+                for c in heartbeat_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid heartbeat interval");
+                    }
+                }
+                let heartbeat_sec = heartbeat_str.parse::<u64>().expect("Could not parse heartbeat interval");
+                heartbeat = Some(Duration::new(heartbeat_sec, 0));
+                args.remove(0);
+            }
             &_ => { panic!("Unexpected arguement"); }
         }
     }
 
     let mut client = Client::new(host, port);
     client.with_timeout(timeout)
-        .with_retries(retries);
+        .with_retries(retries)
+        .with_id(id.clone());
+    if let Some(interval) = heartbeat {
+        // Keep the lease alive while long running jobs execute
+        client.with_heartbeat(interval);
+    }
 
     loop {
         match client.query() {
             GetJobResult::JobLoaded => {
-                client.respond(format!("Client {id} responded to job {}", client.job.clone().unwrap().to_uri()));
+                client.process(|job| format!("Client {id} responded to job {}", job.to_uri())).expect("Error when sending job response");
             }
             GetJobResult::NoJobsLeft => {
                 println!("Server reports no jobs left for client {id}. Client shutting down...");
                 break;
             }
+            GetJobResult::Wait(duration) => {
+                // Other clients still hold jobs that may be abandoned
+                sleep(duration);
+                continue;
+            }
             GetJobResult::Error => {
                 panic!("Error encountered");
             }
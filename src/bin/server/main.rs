@@ -1,18 +1,32 @@
 use std::{
-    env, process::exit, sync::{Arc, Mutex}, time::Duration
+    env, fs, path::PathBuf, process::exit, sync::{Arc, Mutex}, time::Duration
 };
 
-use netspatch::{job::JobManager, server::Server};
+use netspatch::{job::{Job, JobManager, LeasePolicy, Schedule}, param::Parameter, server::{Server, ServerOptions}, sink::{DirectorySink, JsonLinesSink, ResultSink, StdoutSink}};
 
 fn main() {
     let mut host = "localhost".to_string();
     let mut port = 7878_u32;
     let mut fuse = Duration::new(0, 0);
+    let mut lease: Option<Duration> = None;
+    let mut max_attempts: Option<usize> = None;
+    let mut speculation: Option<usize> = None;
+    let mut lease_policy = LeasePolicy::Current;
+    let mut min_chunk: Option<usize> = None;
+    let mut workers: Option<usize> = None;
+    let mut jobs_path: Option<String> = None;
+    let mut parameters: Vec<Parameter> = Vec::new();
+    let mut sink: Box<dyn ResultSink> = Box::new(StdoutSink);
+    let mut state_path: Option<PathBuf> = None;
+    let mut checkpoint_interval = Duration::new(30, 0);
+    let mut journal_path: Option<PathBuf> = None;
+    let mut options = ServerOptions::new();
 
     let mut args: Vec<String> = env::args().collect();
     args.remove(0);
 
     let mut dimensions: Vec<usize> = Vec::new();
+    let mut chunks: Vec<usize> = Vec::new();
 
     while args.len() > 0 {
         match args.first().unwrap().as_str() {
@@ -46,26 +60,311 @@ fn main() {
                 fuse = Duration::new(fuse_len, 0);
                 args.remove(0);
             }
+            "--threads" => {
+                args.remove(0);
+                let threads_str = args.first().unwrap().to_string();
+                for c in threads_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid thread count");
+                    }
+                }
+                options.with_threads(threads_str.parse::<usize>().expect("Could not parse thread count"));
+                args.remove(0);
+            }
+            "--read-timeout" | "--write-timeout" => {
+                let flag = args.remove(0);
+                let timeout_str = args.first().unwrap().to_string();
+                for c in timeout_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid timeout");
+                    }
+                }
+                // Zero disables the timeout
+                let timeout = match timeout_str.parse::<u64>().expect("Could not parse timeout") {
+                    0 => None,
+                    seconds => Some(Duration::new(seconds, 0)),
+                };
+                if flag == "--read-timeout" {
+                    options.with_read_timeout(timeout);
+                } else {
+                    options.with_write_timeout(timeout);
+                }
+                args.remove(0);
+            }
+            "--keep-alive" => {
+                args.remove(0);
+                let idle_str = args.first().unwrap().to_string();
+                for c in idle_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid keep-alive");
+                    }
+                }
+                // Zero closes every connection after one request
+                options.with_keep_alive(match idle_str.parse::<u64>().expect("Could not parse keep-alive") {
+                    0 => None,
+                    seconds => Some(Duration::new(seconds, 0)),
+                });
+                args.remove(0);
+            }
+            "--max-batch" => {
+                args.remove(0);
+                let batch_str = args.first().unwrap().to_string();
+                for c in batch_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid batch size");
+                    }
+                }
+                options.with_max_batch(batch_str.parse::<usize>().expect("Could not parse batch size"));
+                args.remove(0);
+            }
+            "--max-headers" => {
+                args.remove(0);
+                let count_str = args.first().unwrap().to_string();
+                for c in count_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid header count");
+                    }
+                }
+                options.with_max_headers(count_str.parse::<usize>().expect("Could not parse header count"));
+                args.remove(0);
+            }
+            "--max-header-size" => {
+                args.remove(0);
+                let size_str = args.first().unwrap().to_string();
+                for c in size_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid header size");
+                    }
+                }
+                options.with_max_header_size(size_str.parse::<usize>().expect("Could not parse header size"));
+                args.remove(0);
+            }
+            "--max-body-size" => {
+                args.remove(0);
+                let size_str = args.first().unwrap().to_string();
+                for c in size_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid body size");
+                    }
+                }
+                options.with_max_body_size(size_str.parse::<usize>().expect("Could not parse body size"));
+                args.remove(0);
+            }
+            "--lease" => {
+                args.remove(0);
+                let lease_str = args.first().unwrap().to_string();
+                for c in lease_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid lease");
+                    }
+                }
+                let lease_len = lease_str.parse::<u64>().expect("Could not parse lease");
+                lease = Some(Duration::new(lease_len, 0));
+                args.remove(0);
+            }
+            "--lease-policy" => {
+                args.remove(0);
+                lease_policy = match args.first().unwrap().as_str() {
+                    "current" => LeasePolicy::Current,
+                    "first-wins" => LeasePolicy::FirstWins,
+                    "last-wins" => LeasePolicy::LastWins,
+                    policy => panic!("Invalid lease policy {}", policy),
+                };
+                args.remove(0);
+            }
+            "--max-attempts" => {
+                args.remove(0);
+                let attempts_str = args.first().unwrap().to_string();
+                for c in attempts_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid attempt count");
+                    }
+                }
+                max_attempts = Some(attempts_str.parse::<usize>().expect("Could not parse attempt count"));
+                args.remove(0);
+            }
+            "--speculate" => {
+                args.remove(0);
+                let copies_str = args.first().unwrap().to_string();
+                for c in copies_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid copy count");
+                    }
+                }
+                speculation = Some(copies_str.parse::<usize>().expect("Could not parse copy count"));
+                args.remove(0);
+            }
+            "--jobs" => {
+                args.remove(0);
+                jobs_path = Some(args.first().unwrap().to_string());
+                args.remove(0);
+            }
+            "--output" => {
+                args.remove(0);
+                let output_str = args.first().unwrap().to_string();
+                // One of "stdout", "jsonl:<file>" or "dir:<directory>"
+                sink = match output_str.split_once(':') {
+                    Some(("jsonl", path)) => Box::new(JsonLinesSink::open(&PathBuf::from(path)).expect("Could not open output file")),
+                    Some(("dir", path)) => Box::new(DirectorySink::new(&PathBuf::from(path)).expect("Could not create output directory")),
+                    _ if output_str == "stdout" => Box::new(StdoutSink),
+                    _ => panic!("Invalid output {}", output_str),
+                };
+                args.remove(0);
+            }
+            "--state" => {
+                args.remove(0);
+                state_path = Some(PathBuf::from(args.first().unwrap()));
+                args.remove(0);
+            }
+            "--journal" => {
+                args.remove(0);
+                journal_path = Some(PathBuf::from(args.first().unwrap()));
+                args.remove(0);
+            }
+            "--checkpoint-interval" => {
+                args.remove(0);
+                let interval_str = args.first().unwrap().to_string();
+                for c in interval_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid checkpoint interval");
+                    }
+                }
+                let interval_len = interval_str.parse::<u64>().expect("Could not parse checkpoint interval");
+                checkpoint_interval = Duration::new(interval_len, 0);
+                args.remove(0);
+            }
+            "--param" => {
+                args.remove(0);
+                let param_str = args.first().unwrap().to_string();
+                match Parameter::parse(&param_str) {
+                    Ok(parameter) => parameters.push(parameter),
+                    Err(_) => panic!("Invalid parameter {}", param_str),
+                }
+                args.remove(0);
+            }
+            "--guided" => {
+                args.remove(0);
+                let chunk_str = args.first().unwrap().to_string();
+                for c in chunk_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid minimum chunk");
+                    }
+                }
+                min_chunk = Some(chunk_str.parse::<usize>().expect("Could not parse minimum chunk"));
+                args.remove(0);
+            }
+            "--workers" => {
+                args.remove(0);
+                let workers_str = args.first().unwrap().to_string();
+                for c in workers_str.chars() {
+                    if !c.is_numeric() { 
+                        panic!("Invalid worker count");
+                    }
+                }
+                workers = Some(workers_str.parse::<usize>().expect("Could not parse worker count"));
+                args.remove(0);
+            }
             dimension => {
-                for c in dimension.chars() {
+                // Dimensions are given as "<span>" or "<span>:<chunk>"
+                let (span_str, chunk_str) = dimension.split_once(':').unwrap_or((dimension, "1"));
+                for c in span_str.chars().chain(chunk_str.chars()) {
                     if !c.is_numeric() { 
                         panic!("Invalid dimension {}", dimension);
                     }
                 }
-                dimensions.push(dimension.parse::<usize>().unwrap());
+                dimensions.push(span_str.parse::<usize>().unwrap());
+                chunks.push(chunk_str.parse::<usize>().unwrap());
                 args.remove(0);
             }
         }
     }
 
-    if dimensions.len() == 0 {
+    // Resume from the state file when one exists
+    let resume = state_path.clone().filter(|path| path.exists());
+    if dimensions.len() == 0 && resume.is_none() {
         eprintln!("No dimensions provided");
         exit(1);
     }
-    assert!(dimensions.len() > 0);
-    let stack = Arc::new(Mutex::new(JobManager::new(&dimensions).unwrap()));
+    let mut manager = match (resume, jobs_path) {
+        (Some(path), _) => {
+            let contents = fs::read_to_string(&path).expect("Could not read state");
+            let manager = JobManager::from_checkpoint(&contents).expect("Could not parse state");
+            if dimensions.len() > 0 && manager.dimensions().ne(&dimensions) {
+                panic!("Dimensions do not match the state in {}", path.display());
+            }
+            eprintln!("Resuming from {}", path.display());
+            manager
+        }
+        (None, Some(path)) => {
+            let contents = fs::read_to_string(&path).expect("Could not read job list");
+            let mut jobs = Vec::new();
+            for line in contents.lines() {
+                // One URI per line, optionally followed by ": <reason>" as printed for failed jobs
+                let uri = line.split(':').next().unwrap().trim();
+                if uri.len() == 0 || uri.starts_with('#') {
+                    continue;
+                }
+                match Job::from_uri(&uri.to_string(), &dimensions) {
+                    Ok(job) => jobs.push(job),
+                    Err(_) => panic!("Invalid job {} in {}", uri, path),
+                }
+            }
+            JobManager::from_jobs(&dimensions, jobs).expect("Invalid job list")
+        }
+        (None, None) => JobManager::chunked(&dimensions, &chunks).expect("Invalid job dimensions"),
+    };
+    if let Some(path) = &state_path {
+        manager.with_checkpoint(path, checkpoint_interval);
+    }
+    if let Some(duration) = lease {
+        manager.with_lease(duration);
+    }
+    if let Some(attempts) = max_attempts {
+        manager.with_max_attempts(attempts);
+    }
+    if let Some(copies) = speculation {
+        // Idle workers duplicate stragglers once nothing else is left to hand out
+        manager.with_speculation(copies);
+    }
+    manager.with_lease_policy(lease_policy);
+    if parameters.len() > 0 {
+        manager.with_parameters(parameters).expect("Parameters do not match the job dimensions");
+    }
+    if let Some(min_chunk) = min_chunk {
+        // Each chunk is the remaining work split across the workers, so the count must be real
+        let workers = match workers {
+            Some(value) if value > 0 => value,
+            _ => {
+                eprintln!("--guided needs --workers set to the number of workers sharing the job space");
+                exit(1);
+            }
+        };
+        manager.with_schedule(Schedule::Guided { min_chunk, workers });
+    }
+    // Replay the journal last so it sees the same configuration that wrote it
+    if let Some(path) = &journal_path {
+        if state_path.is_none() {
+            eprintln!("--journal needs --state, which is where the journal is folded in and truncated");
+            exit(1);
+        }
+        manager.with_journal(path).expect("Could not replay journal");
+    }
+    let stack = Arc::new(Mutex::new(manager));
 
-    let server = Server::start(&host, port, stack, fuse).expect("Could not start server");
+    let server = Server::start_with_options(&host, port, stack.clone(), fuse, sink, &options).expect("Could not start server");
 
     server.wait();
+
+    // Report the jobs that ran out of attempts so they can be re-run
+    let failed = stack.lock().unwrap().jobs_failed();
+    if failed.len() > 0 {
+        eprintln!("{} job(s) failed:", failed.len());
+        let manager = stack.lock().unwrap();
+        for job in failed {
+            match manager.reason(&job) {
+                Some(reason) => eprintln!("  {}: {}", job.to_uri(), reason),
+                None => eprintln!("  {}", job.to_uri()),
+            }
+        }
+    }
 }
@@ -1,19 +1,25 @@
-use std::{io::{BufReader, Write}, net::{TcpStream, ToSocketAddrs}, time::Duration};
+use std::{io::{BufReader, Write}, net::{TcpStream, ToSocketAddrs}, sync::mpsc::{self, RecvTimeoutError}, thread, time::Duration};
 
-use crate::{http::{HTTPMethod, HTTPRequest, HTTPResponse, HTTPResponseCode}, job::Job};
+use crate::{http::{HTTPMessage, HTTPMethod, HTTPRequest, HTTPResponse, HTTPResponseCode}, job::{Job, JobResult, LEASE_HEADER}, worker::WORKER_HEADER};
 
 pub struct Client {
     host: String,
     port: u32,
     pub job: Option<Job>,
+    pub jobs: Vec<Job>,
     timeout: Duration,
     retries: u64,
+    heartbeat: Option<Duration>,
+    id: Option<String>,
+    keep_alive: bool,
+    connection: Option<BufReader<TcpStream>>,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum GetJobResult {
     JobLoaded,
     NoJobsLeft,
+    Wait(Duration),
     Error,
 }
 
@@ -29,8 +35,13 @@ impl Client {
             host,
             port,
             job: None,
+            jobs: Vec::new(),
             timeout: Duration::new(1, 0),
-            retries: 0
+            retries: 0,
+            heartbeat: None,
+            id: None,
+            keep_alive: true,
+            connection: None,
         };
     }
 
@@ -44,6 +55,24 @@ impl Client {
         self
     }
 
+    pub fn with_id(&mut self, id: String) -> &mut Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn with_heartbeat(&mut self, interval: Duration) -> &mut Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    pub fn with_keep_alive(&mut self, keep_alive: bool) -> &mut Self {
+        self.keep_alive = keep_alive;
+        if !keep_alive {
+            self.connection = None;
+        }
+        self
+    }
+
     fn connect(&self) -> Result<TcpStream, std::io::Error> {
         // Build the uri
         let uri = format!("{}:{}", self.host, self.port);
@@ -80,28 +109,54 @@ impl Client {
         return Err(err);
     }
 
-    pub fn send(&mut self, request: HTTPRequest) -> Result<HTTPResponse, std::io::Error> {
-        let mut stream = self.connect()?;
-        
-        // Send the request
-        stream.write_all(request.to_string().as_bytes())?;
+    pub fn send(&mut self, mut request: HTTPRequest) -> Result<HTTPResponse, std::io::Error> {
+        // Identify the host and the worker on every request
+        request.headers.insert("Host".to_string(), format!("{}:{}", self.host, self.port));
+        if let Some(id) = &self.id {
+            request.headers.insert(WORKER_HEADER.to_string(), id.clone());
+        }
+        let connection = if self.keep_alive { "keep-alive" } else { "close" };
+        request.headers.insert("Connection".to_string(), connection.to_string());
+        let message = request.to_bytes();
 
-        // Build the reader
-        let buf_reader = BufReader::new(&stream);
+        // The server may have dropped an idle connection, which only shows once it is used
+        if let Some(reader) = self.connection.take() {
+            match self.exchange(reader, &message) {
+                Ok(value) => return Ok(value),
+                Err(raw) if raw.len() == 0 => (),
+                Err(raw) => return Err(Self::parse_error(raw)),
+            }
+        }
 
-        // Get the response
-        return match HTTPResponse::read(buf_reader) {
+        let reader = BufReader::new(self.connect()?);
+        return match self.exchange(reader, &message) {
             Ok(value) => Ok(value),
-            Err(raw) => {
-                eprintln!("Error parsing HTTP response:");
-                for line in raw {
-                    eprintln!("  {line}");
-                }
-                return Err(std::io::Error::last_os_error())
-            }
+            Err(raw) => Err(Self::parse_error(raw)),
         };
     }
 
+    fn exchange(&mut self, mut reader: BufReader<TcpStream>, message: &Vec<u8>) -> Result<HTTPResponse, Vec<String>> {
+        // Send the request
+        if reader.get_mut().write_all(message).is_err() {
+            return Err(Vec::new());
+        }
+
+        // Get the response and hold on to the connection if both sides agree
+        let response = HTTPResponse::read(&mut reader)?;
+        if self.keep_alive && response.keeps_alive() {
+            self.connection = Some(reader);
+        }
+        return Ok(response);
+    }
+
+    fn parse_error(raw: Vec<String>) -> std::io::Error {
+        eprintln!("Error parsing HTTP response:");
+        for line in raw {
+            eprintln!("  {line}");
+        }
+        return std::io::Error::last_os_error();
+    }
+
     pub fn query(&mut self) -> GetJobResult {
         // Clear the current job
         self.job = None;
@@ -109,6 +164,25 @@ impl Client {
         // Build the request
         let request = HTTPRequest::new(HTTPMethod::GET, "".to_string());
 
+        let result = self.fetch(request);
+        if result.success() {
+            self.job = self.jobs.pop();
+        }
+        return result;
+    }
+
+    pub fn query_batch(&mut self, count: usize) -> GetJobResult {
+        assert!(count > 0);
+
+        // Build the request
+        let request = HTTPRequest::new(HTTPMethod::GET, format!("batch/{count}"));
+        return self.fetch(request);
+    }
+
+    fn fetch(&mut self, request: HTTPRequest) -> GetJobResult {
+        // Clear the current jobs
+        self.jobs.clear();
+
         // Send the request
         let response = match self.send(request) {
             Ok(value) => value,
@@ -121,17 +195,26 @@ impl Client {
         // Handle the response
         match response.status {
             HTTPResponseCode::OK => {
-                let job = Job::parse(&response.content);
-                if job.is_err() {
+                let jobs = match response.text() {
+                    Some(text) => Job::parse_list(&text.to_string()),
+                    None => return GetJobResult::Error,
+                };
+                if jobs.is_err() || jobs.as_ref().unwrap().len() == 0 {
                     return GetJobResult::Error;
                 }
-                self.job = Some(job.unwrap());
+                self.jobs = jobs.unwrap();
                 return GetJobResult::JobLoaded;
             }
             HTTPResponseCode::NoContent => {
-                self.job = None;
                 return GetJobResult::NoJobsLeft;
             }
+            HTTPResponseCode::ServiceUnavailable => {
+                let seconds = match response.header("Retry-After") {
+                    Some(value) => value.parse::<u64>().unwrap_or(1),
+                    None => 1,
+                };
+                return GetJobResult::Wait(Duration::new(seconds, 0));
+            }
             _default => {
                 eprintln!("Unexpected response code encountered");
                 return GetJobResult::Error;
@@ -139,7 +222,11 @@ impl Client {
         }
     }
 
-    pub fn respond(&mut self, result: String) {
+    pub fn respond(&mut self, result: String) -> Result<HTTPResponseCode, std::io::Error> {
+        return self.respond_bytes(result.into_bytes());
+    }
+
+    pub fn respond_bytes(&mut self, result: Vec<u8>) -> Result<HTTPResponseCode, std::io::Error> {
         if self.job.is_none() {
             panic!("Attempted to respond when no job is loaded");
         }
@@ -148,9 +235,101 @@ impl Client {
         let job = self.job.clone().unwrap();
         let mut request = HTTPRequest::new(HTTPMethod::POST, job.to_uri());
         request.body = result;
+        if let Some(lease) = job.lease {
+            request.headers.insert(LEASE_HEADER.to_string(), lease.to_string());
+        }
+
+        // Send the request, a rejected result (such as 409 for a duplicate) is still settled
+        let response = self.send(request)?;
+        self.job = None;
+        return Ok(response.status);
+    }
+
+    pub fn heartbeat(&mut self) -> bool {
+        if self.job.is_none() {
+            panic!("Attempted to renew a lease when no job is loaded");
+        }
+
+        // Build the request
+        let job = self.job.clone().unwrap();
+        let mut request = HTTPRequest::new(HTTPMethod::POST, format!("renew/{}", job.to_uri()));
+        if let Some(lease) = job.lease {
+            request.headers.insert(LEASE_HEADER.to_string(), lease.to_string());
+        }
+
+        // Send the request
+        return match self.send(request) {
+            Ok(response) => matches!(response.status, HTTPResponseCode::OK),
+            Err(err) => {
+                eprintln!("Error during heartbeat: {}", err);
+                false
+            }
+        };
+    }
+
+    pub fn process<F: FnOnce(&Job) -> B, B: Into<Vec<u8>>>(&mut self, work: F) -> Result<HTTPResponseCode, std::io::Error> {
+        if self.job.is_none() {
+            panic!("Attempted to process when no job is loaded");
+        }
+        let job = self.job.clone().unwrap();
+        let interval = match self.heartbeat {
+            Some(value) => value,
+            None => {
+                let result = work(&job);
+                return self.respond_bytes(result.into());
+            }
+        };
+
+        // Renew the lease from a second connection while the work runs
+        let mut renewer = Client::new(self.host.clone(), self.port);
+        renewer.with_timeout(self.timeout).with_retries(self.retries);
+        renewer.job = Some(job.clone());
+        renewer.id = self.id.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if !renewer.heartbeat() {
+                    eprintln!("Could not renew lease for job {}", renewer.job.as_ref().unwrap().to_uri());
+                }
+            }
+        });
+        let result = work(&job);
+        drop(stop);
+        handle.join().expect("Heartbeat thread panicked");
+        return self.respond_bytes(result.into());
+    }
+
+    pub fn fail(&mut self, reason: String) -> Result<HTTPResponseCode, std::io::Error> {
+        if self.job.is_none() {
+            panic!("Attempted to report a failure when no job is loaded");
+        }
+
+        // Build the request
+        let job = self.job.clone().unwrap();
+        let mut request = HTTPRequest::new(HTTPMethod::POST, format!("fail/{}", job.to_uri()));
+        request.body = reason.into_bytes();
+        if let Some(lease) = job.lease {
+            request.headers.insert(LEASE_HEADER.to_string(), lease.to_string());
+        }
 
         // Send the request
-        self.send(request).expect("Error when sending job response");
+        let response = self.send(request)?;
         self.job = None;
+        return Ok(response.status);
+    }
+
+    pub fn respond_batch(&mut self, results: Vec<JobResult>) -> Result<HTTPResponseCode, std::io::Error> {
+        if results.len() == 0 {
+            return Ok(HTTPResponseCode::OK);
+        }
+
+        // Build the request
+        let mut request = HTTPRequest::new(HTTPMethod::POST, "batch".to_string());
+        request.body = JobResult::list_to_bytes(&results);
+
+        // Send the request
+        let response = self.send(request)?;
+        self.jobs.retain(|job| !results.iter().any(|result| result.uri == job.to_uri()));
+        return Ok(response.status);
     }
 }
\ No newline at end of file
@@ -0,0 +1,186 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{job::{JobManager, PointState}, status::Status, worker::WorkerRegistry};
+
+// Larger job spaces are folded into blocks so the page stays small
+const MAX_CELLS: usize = 100;
+
+// Failures listed on the page
+const MAX_FAILURES: usize = 50;
+
+// Larger spaces are left off the heat-map, which walks every point
+const MAX_MAPPED_POINTS: usize = 1 << 22;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+td,th{border:1px solid #ccc;padding:4px 8px;text-align:left}\
+.bar{width:100%;height:24px;background:#eee;border-radius:4px;overflow:hidden}\
+.bar div{height:100%;background:#4caf50}\
+.grid{display:grid;gap:1px;max-width:800px}\
+.grid i{display:block;aspect-ratio:1}\
+.a{background:#ddd}.p{background:#ffc107}.d{background:#4caf50}.f{background:#e53935}.x{background:#fff}";
+
+fn escape(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    return result;
+}
+
+fn seconds(duration: Duration) -> String {
+    return format!("{:.1}s", duration.as_secs_f64());
+}
+
+pub fn render(manager: &JobManager, workers: &WorkerRegistry, status: &Status) -> String {
+    let mut result = String::new();
+    result.push_str("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"5\">");
+    result.push_str(&format!("<title>netspatch</title><style>{STYLE}</style></head><body><h1>netspatch</h1>"));
+
+    // Progress
+    let fraction = if status.total > 0 { status.completed as f64 / status.total as f64 } else { 1.0 };
+    result.push_str(&format!("<div class=\"bar\"><div style=\"width:{:.2}%\"></div></div>", fraction * 100.0));
+    let eta = match status.eta {
+        Some(value) => seconds(value),
+        None => "unknown".to_string(),
+    };
+    result.push_str(&format!(
+        "<p>{} of {} completed ({:.1}%), {} pending, {} abandoned, {} failed, {:.2}/s, ETA {}</p>",
+        status.completed, status.total, fraction * 100.0, status.pending, status.abandoned, status.failed, status.throughput, eta
+    ));
+
+    // Workers
+    result.push_str("<h2>Workers</h2><table><tr><th>Worker</th><th>Last contact</th><th>Held</th><th>Completed</th><th>Failed</th><th>Mean duration</th></tr>");
+    for worker in workers.workers() {
+        let mut held: Vec<String> = worker.held.keys().map(|job| job.to_uri()).collect();
+        held.sort();
+        let mean = match worker.mean_duration() {
+            Some(value) => seconds(value),
+            None => "-".to_string(),
+        };
+        let idle = SystemTime::now().duration_since(worker.last_contact).unwrap_or(Duration::ZERO);
+        result.push_str(&format!(
+            "<tr><td>{}</td><td>{} ago</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&worker.id), seconds(idle), escape(&held.join(" ")), worker.completed, worker.failed, mean
+        ));
+    }
+    result.push_str("</table>");
+
+    // Failures
+    let failed = manager.jobs_failed();
+    result.push_str("<h2>Failures</h2><table><tr><th>Job</th><th>State</th><th>Attempts</th><th>Reason</th></tr>");
+    for (job, reason) in manager.failures().iter().take(MAX_FAILURES) {
+        let state = if failed.contains(job) { "failed" } else { "retrying" };
+        result.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&job.to_uri()), state, manager.attempts(job), escape(reason)
+        ));
+    }
+    result.push_str("</table>");
+
+    // Heat-map of the job space
+    let dimensions = manager.dimensions();
+    if dimensions.len() == 2 && dimensions[0] * dimensions[1] <= MAX_MAPPED_POINTS {
+        result.push_str(&heat_map(&manager.point_states(), dimensions[0], dimensions[1]));
+    }
+    result.push_str("</body></html>");
+    return result;
+}
+
+fn heat_map(states: &Vec<PointState>, rows: usize, columns: usize) -> String {
+    let row_block = rows.div_ceil(MAX_CELLS);
+    let column_block = columns.div_ceil(MAX_CELLS);
+    let grid_rows = rows.div_ceil(row_block);
+    let grid_columns = columns.div_ceil(column_block);
+
+    let mut result = "<h2>Job space</h2>".to_string();
+    if row_block > 1 || column_block > 1 {
+        result.push_str(&format!("<p>Each cell covers {row_block}x{column_block} points</p>"));
+    }
+    result.push_str(&format!("<div class=\"grid\" style=\"grid-template-columns:repeat({grid_columns},1fr)\">"));
+    for grid_row in 0..grid_rows {
+        for grid_column in 0..grid_columns {
+            // A block shows its most pressing state
+            let mut any_failed = false;
+            let mut any_pending = false;
+            let mut all_done = true;
+            let mut any_listed = false;
+            for row in grid_row * row_block..((grid_row + 1) * row_block).min(rows) {
+                for column in grid_column * column_block..((grid_column + 1) * column_block).min(columns) {
+                    let state = states[row * columns + column];
+                    any_listed |= state != PointState::Unlisted;
+                    match state {
+                        PointState::Failed => any_failed = true,
+                        PointState::Pending => any_pending = true,
+                        PointState::Available => all_done = false,
+                        PointState::Done | PointState::Unlisted => (),
+                    }
+                }
+            }
+            let class = if !any_listed {
+                "x"
+            } else if any_failed {
+                "f"
+            } else if any_pending {
+                "p"
+            } else if all_done {
+                "d"
+            } else {
+                "a"
+            };
+            result.push_str(&format!("<i class=\"{class}\"></i>"));
+        }
+    }
+    result.push_str("</div>");
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::Throughput;
+
+    #[test]
+    fn test_dashboard() {
+        let dimensions = vec![2, 3];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        let job = manager.pop().unwrap();
+        manager.complete(job.to_uri()).unwrap();
+        let failed = manager.pop().unwrap();
+        manager.fail_with_lease(failed.to_uri(), "<oops>".to_string(), failed.lease).unwrap();
+        manager.pop().unwrap();
+        let mut workers = WorkerRegistry::new();
+        workers.contact(&"a&b".to_string());
+
+        let status = Status::new(&manager, &mut Throughput::new(Duration::from_secs(60)));
+        let page = render(&manager, &workers, &status);
+        assert!(page.contains("1 of 6 completed"));
+        assert!(page.contains("<td>a&amp;b</td>"));
+        assert!(page.contains("<td>retrying</td><td>2</td><td>&lt;oops&gt;</td>"));
+        assert!(page.contains("repeat(3,1fr)"));
+        assert_eq!(page.matches("<i class=\"d\">").count(), 1);
+        assert_eq!(page.matches("<i class=\"p\">").count(), 1);
+        assert_eq!(page.matches("<i class=\"a\">").count(), 4);
+    }
+
+    #[test]
+    fn test_heat_map_blocks() {
+        let mut states = vec![PointState::Done; 250 * 10];
+        states[0] = PointState::Failed;
+        let map = heat_map(&states, 250, 10);
+        assert!(map.contains("Each cell covers 3x1 points"));
+        assert_eq!(map.matches("<i ").count(), 84 * 10);
+        assert_eq!(map.matches("<i class=\"f\">").count(), 1);
+
+        // Points outside a job list are left blank
+        let states = vec![PointState::Unlisted, PointState::Done, PointState::Unlisted, PointState::Unlisted];
+        assert_eq!(heat_map(&states, 2, 2).matches("<i class=\"x\">").count(), 3);
+    }
+}
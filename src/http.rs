@@ -1,4 +1,4 @@
-use std::{collections::HashMap, io::{self, BufRead, BufReader, Read}, net::TcpStream, str};
+use std::{collections::HashMap, io::{self, BufRead, Read}};
 
 #[derive(Clone, PartialEq)]
 pub enum HTTPMethod {
@@ -24,27 +24,39 @@ pub struct RequestLine {
 
 impl RequestLine {
     pub fn parse(input: &String) -> Result<Self, HTTPResponseCode> {
-        // Parse the method
-        if !input.contains(' ') {
+        let parts: Vec<&str> = input.split(' ').collect();
+        if parts.len() != 3 {
             return Err(HTTPResponseCode::BadRequest);
         }
-        let (method_str, rem) =  input.split_once(' ').unwrap();
-        let method = match method_str {
+
+        // Check the version before anything else
+        let version = parts[2];
+        if !version.starts_with("HTTP/") {
+            return Err(HTTPResponseCode::BadRequest);
+        } else if version != "HTTP/1.1" && version != "HTTP/1.0" {
+            return Err(HTTPResponseCode::HTTPVersionNotSupported);
+        }
+
+        // Parse the method
+        let method = match parts[0] {
             "GET" => HTTPMethod::GET,
             "POST" => HTTPMethod::POST,
             &_ => return Err(HTTPResponseCode::MethodNotAllowed)
         };
 
-        // Parse path
-        if !rem.contains(' ') {
-            return Err(HTTPResponseCode::BadRequest);
-        }
-        let (fullpath, version) = rem.split_once(' ').unwrap();
-        assert!(fullpath.len() > 0);
-        if fullpath.chars().next().unwrap() != '/' {
-            return Err(HTTPResponseCode::BadRequest);
+        // Proxies may send the absolute form of the target
+        let mut target = parts[1];
+        if let Some(rem) = target.strip_prefix("http://").or_else(|| target.strip_prefix("https://")) {
+            target = match rem.find('/') {
+                Some(index) => &rem[index..],
+                None => "/",
+            };
         }
-        let (_, path) = fullpath.split_once('/').unwrap();
+        let path = match target.strip_prefix('/') {
+            Some(value) => value,
+            None => return Err(HTTPResponseCode::BadRequest),
+        };
+        let path = path.split('?').next().unwrap_or("");
         return Ok(Self {
             method,
             path: path.to_string(),
@@ -53,29 +65,177 @@ impl RequestLine {
     }
 }
 
+// Lines longer than this are rejected when no other limit applies
+const MAX_LINE: usize = 64 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct RequestLimits {
+    pub max_headers: usize,
+    pub max_header_size: usize,
+    pub max_body_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        return Self {
+            max_headers: 64,
+            max_header_size: 16 * 1024,
+            max_body_size: 64 * 1024 * 1024,
+        };
+    }
+}
+
+// Reads that hit the socket timeout surface as one of these kinds
+fn read_error(err: &io::Error) -> HTTPResponseCode {
+    return match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => HTTPResponseCode::RequestTimeout,
+        _ => HTTPResponseCode::BadRequest,
+    };
+}
+
+// Returns the line without its terminator and the number of bytes consumed
+fn read_line<R: BufRead>(reader: &mut R, limit: usize) -> Result<(String, usize), HTTPResponseCode> {
+    let mut line = String::new();
+    let read = match reader.by_ref().take(limit as u64).read_line(&mut line) {
+        Ok(value) => value,
+        Err(err) => return Err(read_error(&err)),
+    };
+    if !line.ends_with('\n') {
+        if read == limit {
+            return Err(HTTPResponseCode::RequestHeaderFieldsTooLarge);
+        }
+        return Err(HTTPResponseCode::BadRequest);
+    }
+    line.pop();
+    if line.ends_with('\r') {
+        line.pop();
+    }
+    return Ok((line, read));
+}
+
+fn parse_header(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once(':')?;
+    // Whitespace before the colon and folded lines are both forbidden
+    if key.len() == 0 || key.contains(|c: char| c.is_whitespace()) {
+        return None;
+    }
+    return Some((key.to_string(), value.trim_matches(|c| c == ' ' || c == '\t').to_string()));
+}
+
+fn parse_headers(raw: &[String]) -> Result<HashMap<String, String>, HTTPResponseCode> {
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for line in raw {
+        let (key, value) = match parse_header(line) {
+            Some(value) => value,
+            None => return Err(HTTPResponseCode::BadRequest),
+        };
+        if headers.keys().any(|existing| existing.eq_ignore_ascii_case(&key)) {
+            return Err(HTTPResponseCode::BadRequest);
+        }
+        headers.insert(key, value);
+    }
+    return Ok(headers);
+}
+
+// HTTP/1.1 connections persist unless closed, older ones only when asked
+fn keeps_alive(version: &str, connection: Option<&String>) -> bool {
+    let connection = match connection {
+        Some(value) => value.to_ascii_lowercase(),
+        None => String::new(),
+    };
+    if version == "HTTP/1.0" {
+        return connection.contains("keep-alive");
+    }
+    return !connection.contains("close");
+}
+
+fn write_headers(result: &mut String, headers: &HashMap<String, String>) {
+    for (key, value) in headers {
+        if key.eq_ignore_ascii_case("Content-Length") {
+            continue;
+        }
+        result.push_str(&format!("{key}: {value}\r\n"));
+    }
+}
+
 pub trait HTTPMessage {
     fn headers(&self) -> &HashMap<String, String>;
 
-    fn expected_body_length(&self) -> usize {
-        let key: String = "Content-Length".to_string();
-        if !self.headers().contains_key(&key) {
-            return 0
+    fn body(&self) -> &Vec<u8>;
+
+    // Bodies are raw bytes, this is for the ones that carry text
+    fn text(&self) -> Option<&str> {
+        return std::str::from_utf8(self.body()).ok();
+    }
+
+    fn header(&self, name: &str) -> Option<&String> {
+        return self.headers().iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value);
+    }
+
+    fn is_chunked(&self) -> bool {
+        return match self.header("Transfer-Encoding") {
+            Some(value) => value.to_ascii_lowercase().contains("chunked"),
+            None => false,
         };
-        let length_str = self.headers().get(&key).unwrap();
-        let err = format!("Could not parse Content-Length: {}", length_str);
-        return length_str.parse::<usize>().expect(&err);
-    }
-
-    fn read_body(&self, mut reader: BufReader<&TcpStream>) -> Result<String, io::Error> {
-        let mut result = String::new();
-        let body_len = self.expected_body_length();
-        while result.len() < body_len {
-            let mut buf  = [0_u8; 1];
-            reader.read_exact(&mut buf)?;
-            result.push_str(str::from_utf8(&buf).expect("UTF8 parsing error"));
+    }
+
+    fn expected_body_length(&self) -> Result<usize, HTTPResponseCode> {
+        return match self.header("Content-Length") {
+            Some(value) => match value.parse::<usize>() {
+                Ok(length) => Ok(length),
+                Err(_) => Err(HTTPResponseCode::BadRequest),
+            },
+            None => Ok(0),
+        };
+    }
+
+    fn read_body<R: BufRead>(&self, reader: &mut R, limit: usize) -> Result<Vec<u8>, HTTPResponseCode> {
+        let mut bytes = Vec::new();
+        if self.is_chunked() {
+            if self.header("Content-Length").is_some() {
+                return Err(HTTPResponseCode::BadRequest);
+            }
+            loop {
+                let (line, _) = read_line(reader, MAX_LINE)?;
+                let size_str = line.split(';').next().unwrap_or("").trim();
+                let size = match usize::from_str_radix(size_str, 16) {
+                    Ok(value) => value,
+                    Err(_) => return Err(HTTPResponseCode::BadRequest),
+                };
+                if size == 0 {
+                    // Trailers are read and dropped
+                    while read_line(reader, MAX_LINE)?.0.len() > 0 {}
+                    break;
+                }
+                // Compared this way round so a hostile size cannot overflow
+                if size > limit - bytes.len() {
+                    return Err(HTTPResponseCode::PayloadTooLarge);
+                }
+                read_exact(reader, &mut bytes, size)?;
+                if read_line(reader, MAX_LINE)?.0.len() > 0 {
+                    return Err(HTTPResponseCode::BadRequest);
+                }
+            }
+        } else {
+            let length = self.expected_body_length()?;
+            if length > limit {
+                return Err(HTTPResponseCode::PayloadTooLarge);
+            }
+            read_exact(reader, &mut bytes, length)?;
         }
-        return Ok(result);
+        return Ok(bytes);
+    }
+}
+
+fn read_exact<R: BufRead>(reader: &mut R, bytes: &mut Vec<u8>, length: usize) -> Result<(), HTTPResponseCode> {
+    let read = match reader.by_ref().take(length as u64).read_to_end(bytes) {
+        Ok(value) => value,
+        Err(err) => return Err(read_error(&err)),
+    };
+    if read < length {
+        return Err(HTTPResponseCode::BadRequest);
     }
+    return Ok(());
 }
 
 pub struct HTTPRequest {
@@ -83,7 +243,7 @@ pub struct HTTPRequest {
     pub uri: String,
     pub version: String,
     pub headers: HashMap<String, String>,
-    pub body: String,
+    pub body: Vec<u8>,
 }
 
 impl HTTPRequest {
@@ -93,28 +253,22 @@ impl HTTPRequest {
             uri,
             version: "HTTP/1.1".to_string(),
             headers: HashMap::new(),
-            body: String::new()
+            body: Vec::new()
         };
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut result = format!("{} /{} {}\r\n", self.method.to_string(), self.uri, self.version);
-        let mut headers = self.headers.clone();
-        if !self.body.is_empty() {
-            if self.method == HTTPMethod::GET {
-                panic!("Attempting to send a body in a GET request");
-            }
-            headers.insert("Content-Length".to_string(), self.body.len().to_string());
-        }
-        for (key, value) in headers {
-            let header = format!("{key}: {value}\r\n").to_string();
-            result.push_str(&header);
+        if self.method == HTTPMethod::GET && !self.body.is_empty() {
+            panic!("Attempting to send a body in a GET request");
         }
-        result.push_str("\r\n");
-        if !self.body.is_empty() {
-            result.push_str(&self.body);
+        write_headers(&mut result, &self.headers);
+        if self.method == HTTPMethod::POST {
+            result.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
         }
         result.push_str("\r\n");
+        let mut result = result.into_bytes();
+        result.extend_from_slice(&self.body);
         return result;
     }
 
@@ -123,49 +277,61 @@ impl HTTPRequest {
             return Err(HTTPResponseCode::BadRequest);
         }
         let line = RequestLine::parse(raw.first().unwrap())?;
-        let mut headers: HashMap<String, String> = HashMap::new();
-        for i in 1..raw.len() {
-            let header = raw.get(i).unwrap();
-            if !header.contains(": ") {
-                return Err(HTTPResponseCode::BadRequest);
-            }
-            let (key, value) = header.split_once(": ").unwrap();
-            if headers.contains_key(key) {
-                return Err(HTTPResponseCode::BadRequest);
-            }
-            headers.insert(key.to_string(), value.to_string());
-        }
         return Ok(Self {
             method: line.method,
             uri: line.path,
             version: line.version,
-            headers,
-            body: String::new(),
+            headers: parse_headers(&raw[1..])?,
+            body: Vec::new(),
         })
     }
 
-    pub fn read(mut reader: BufReader<&TcpStream>) -> Result<HTTPRequest, HTTPResponseCode> {
-        let mut raw = Vec::new();
+    pub fn read<R: BufRead>(reader: R) -> Result<HTTPRequest, HTTPResponseCode> {
+        return Self::read_with_limits(reader, &RequestLimits::default());
+    }
+
+    pub fn read_with_limits<R: BufRead>(mut reader: R, limits: &RequestLimits) -> Result<HTTPRequest, HTTPResponseCode> {
+        let mut request = Self::read_head(&mut reader, limits)?;
+        request.read_content(&mut reader, limits)?;
+        return Ok(request);
+    }
+
+    pub fn read_head<R: BufRead>(reader: &mut R, limits: &RequestLimits) -> Result<HTTPRequest, HTTPResponseCode> {
+        let mut raw: Vec<String> = Vec::new();
+        let mut header_size = 0;
         loop {
-            let mut line = String::new();
-            reader.read_line(&mut line).expect("Could not read line");
-            if line == "\r\n" {
+            // Never buffer more of the head than the limit allows
+            let (line, read) = read_line(reader, limits.max_header_size - header_size)?;
+            header_size += read;
+            if line.len() == 0 {
+                // Empty lines ahead of the request line are ignored
+                if raw.len() == 0 {
+                    continue;
+                }
                 break;
-            } else if line.len() == 0 {
-                return Err(HTTPResponseCode::BadRequest);
             }
-            line.pop();
-            line.pop();
             raw.push(line);
+            if raw.len() > limits.max_headers + 1 {
+                return Err(HTTPResponseCode::RequestHeaderFieldsTooLarge);
+            }
         }
-        let mut request = HTTPRequest::parse(raw)?;
+        return HTTPRequest::parse(raw);
+    }
 
-        // Get the body
-        request.body = match request.read_body(reader) {
-            Ok(value) => value,
-            Err(_) => return Err(HTTPResponseCode::BadRequest),
+    pub fn read_content<R: BufRead>(&mut self, reader: &mut R, limits: &RequestLimits) -> Result<(), HTTPResponseCode> {
+        self.body = self.read_body(reader, limits.max_body_size)?;
+        return Ok(());
+    }
+
+    pub fn keeps_alive(&self) -> bool {
+        return keeps_alive(&self.version, self.header("Connection"));
+    }
+
+    pub fn expects_continue(&self) -> bool {
+        return match self.header("Expect") {
+            Some(value) => value.eq_ignore_ascii_case("100-continue"),
+            None => false,
         };
-        return Ok(request);
     }
 }
 
@@ -173,18 +339,28 @@ impl HTTPMessage for HTTPRequest {
     fn headers(&self) -> &HashMap<String, String> {
         return &self.headers;
     }
+
+    fn body(&self) -> &Vec<u8> {
+        return &self.body;
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum HTTPResponseCode {
     OK,
     NoContent,
     BadRequest,
     NotFound,
     MethodNotAllowed,
+    RequestTimeout,
     Conflict,
+    PayloadTooLarge,
+    RequestHeaderFieldsTooLarge,
     InternalServerError,
-    HTTPVersionNotSupported
+    ServiceUnavailable,
+    HTTPVersionNotSupported,
+    // Any other valid code, known only by its class
+    Other(u32),
 }
 
 impl HTTPResponseCode {
@@ -195,9 +371,14 @@ impl HTTPResponseCode {
             400 => Some(HTTPResponseCode::BadRequest),
             404 => Some(HTTPResponseCode::NotFound),
             405 => Some(HTTPResponseCode::MethodNotAllowed),
+            408 => Some(HTTPResponseCode::RequestTimeout),
             409 => Some(HTTPResponseCode::Conflict),
+            413 => Some(HTTPResponseCode::PayloadTooLarge),
+            431 => Some(HTTPResponseCode::RequestHeaderFieldsTooLarge),
             500 => Some(HTTPResponseCode::InternalServerError),
+            503 => Some(HTTPResponseCode::ServiceUnavailable),
             505 => Some(HTTPResponseCode::HTTPVersionNotSupported),
+            100..=599 => Some(HTTPResponseCode::Other(code)),
             _ => None,
         };
     }
@@ -217,22 +398,38 @@ impl HTTPResponseCode {
             Self::BadRequest => "Bad Request".to_string(),
             Self::NotFound => "Not Found".to_string(),
             Self::MethodNotAllowed => "Method Not Allowed".to_string(),
+            Self::RequestTimeout => "Request Timeout".to_string(),
             Self::Conflict => "Conflict".to_string(),
+            Self::PayloadTooLarge => "Payload Too Large".to_string(),
+            Self::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large".to_string(),
             Self::InternalServerError => "Internal Server Error".to_string(),
+            Self::ServiceUnavailable => "Service Unavailable".to_string(),
             Self::HTTPVersionNotSupported => "HTTP Version Not Supported".to_string(),
+            Self::Other(code) => match code / 100 {
+                1 => "Informational".to_string(),
+                2 => "Success".to_string(),
+                3 => "Redirection".to_string(),
+                4 => "Client Error".to_string(),
+                _ => "Server Error".to_string(),
+            },
         }
     }
 
-    fn to_code(&self) -> i32 {
+    pub fn to_code(&self) -> i32 {
         return match self {
             Self::OK => 200,
             Self::NoContent => 204,
             Self::BadRequest => 400,
             Self::NotFound => 404,
             Self::MethodNotAllowed => 405,
+            Self::RequestTimeout => 408,
             Self::Conflict => 409,
+            Self::PayloadTooLarge => 413,
+            Self::RequestHeaderFieldsTooLarge => 431,
             Self::InternalServerError => 500,
-            Self::HTTPVersionNotSupported => 505
+            Self::ServiceUnavailable => 503,
+            Self::HTTPVersionNotSupported => 505,
+            Self::Other(code) => *code as i32,
         }
     }
 }
@@ -242,7 +439,7 @@ pub struct HTTPResponse {
     version: String,
     pub status: HTTPResponseCode,
     pub headers: HashMap<String, String>,
-    pub content: String,
+    pub content: Vec<u8>,
 }
 
 impl HTTPResponse {
@@ -251,76 +448,74 @@ impl HTTPResponse {
             version: "HTTP/1.1".to_string(),
             status: code,
             headers: HashMap::new(),
-            content: String::new()
+            content: Vec::new()
         };
     }
 
     pub fn parse(raw: Vec<String>) -> Option<Self> {
-        if raw.len() == 0 { 
-            return None; 
+        if raw.len() == 0 {
+            return None;
         }
+
+        // The reason phrase is optional
         let startline = raw.first()?;
         let (version, rem) = startline.split_once(' ')?;
-        let (code, _) = rem.split_once(' ')?;
-        let mut headers = HashMap::new();
-        for i in 1..raw.len() {
-            let (key, value) = raw.get(i)?.split_once(": ")?;
-            let value_string = value.to_string();
-            headers.insert(key.to_string(), value_string);
+        let code = rem.split(' ').next()?;
+        if !version.starts_with("HTTP/") {
+            return None;
         }
         return Some(Self {
             version: version.to_string(),
             status: HTTPResponseCode::from_string(code.to_string())?,
-            headers,
-            content: String::new()
+            headers: parse_headers(&raw[1..]).ok()?,
+            content: Vec::new()
         });
     }
 
-    pub fn read(mut reader: BufReader<&TcpStream>) -> Result<HTTPResponse, Vec<String>> {
+    pub fn read<R: BufRead>(mut reader: R) -> Result<HTTPResponse, Vec<String>> {
         let mut raw = Vec::new();
         loop {
-            let mut line = String::new();
-            reader.read_line(&mut line).expect("Could not read line");
-            if line == "\r\n" {
+            let line = match read_line(&mut reader, MAX_LINE) {
+                Ok((value, _)) => value,
+                Err(_) => return Err(raw),
+            };
+            if line.len() == 0 {
                 break;
-            } else if line.len() == 0 {
-                return Err(raw.clone());
             }
-            line.pop();
-            line.pop();
             raw.push(line);
         }
         let mut response = match HTTPResponse::parse(raw.clone()) {
             Some(value) => value,
-            None => { return Err(raw.clone()); }
-        };
-
-        // Get the body
-        response.content = match response.read_body(reader) {
-            Ok(value) => value,
-            Err(_) => return Err(raw.clone()),
+            None => { return Err(raw); }
         };
 
+        // Get the body, which runs to the end of the stream when its length is not given
+        if response.status == HTTPResponseCode::NoContent {
+            return Ok(response);
+        } else if response.is_chunked() || response.header("Content-Length").is_some() {
+            response.content = match response.read_body(&mut reader, usize::MAX) {
+                Ok(value) => value,
+                Err(_) => return Err(raw),
+            };
+        } else if reader.read_to_end(&mut response.content).is_err() {
+            return Err(raw);
+        }
         return Ok(response);
     }
 
-    pub fn as_string(&self) -> String {
+    pub fn keeps_alive(&self) -> bool {
+        return keeps_alive(&self.version, self.header("Connection"));
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
         let mut result = format!("{} {} {}\r\n", self.version, self.status.to_code(), self.status.to_string());
-        let mut headers = self.headers.clone();
-        if !headers.contains_key("Content-Length") {
-            headers.insert("Content-Length".to_string(), self.content.len().to_string());
-        } else {
-            *headers.get_mut("Content-Length").unwrap() = self.content.len().to_string();
-        }
-        for (key, value) in headers {
-            let header = format!("{key}: {value}\r\n").to_string();
-            result.push_str(&header);
-        }
-        if self.content.len() > 0 {
-            result.push_str("\r\n");
-            result.push_str(&self.content);
+        write_headers(&mut result, &self.headers);
+        if self.status != HTTPResponseCode::NoContent {
+            result.push_str(&format!("Content-Length: {}\r\n", self.content.len()));
         }
         result.push_str("\r\n");
+        let mut result = result.into_bytes();
+        result.extend_from_slice(&self.content);
         return result;
     }
 }
@@ -329,4 +524,173 @@ impl HTTPMessage for HTTPResponse {
     fn headers(&self) -> &HashMap<String, String> {
         return &self.headers;
     }
-}
\ No newline at end of file
+
+    fn body(&self) -> &Vec<u8> {
+        return &self.content;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::{BufReader, Write}, net::{TcpListener, TcpStream}, time::Duration};
+
+    fn read_raw(raw: &[u8], limits: &RequestLimits) -> Result<HTTPRequest, HTTPResponseCode> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(raw).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        return HTTPRequest::read_with_limits(BufReader::new(&stream), limits);
+    }
+
+    fn read_request(raw: &str) -> Result<HTTPRequest, HTTPResponseCode> {
+        return HTTPRequest::read(raw.as_bytes());
+    }
+
+    #[test]
+    fn test_request_limits() {
+        let limits = RequestLimits {
+            max_headers: 2,
+            max_header_size: 64,
+            max_body_size: 4,
+        };
+        let request = read_raw(b"POST /0 HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody", &limits).ok().unwrap();
+        assert_eq!(request.body, b"body");
+
+        let codes = [
+            (&b"POST /0 HTTP/1.1\r\nContent-Length: 5\r\n\r\nbody!"[..], 413),
+            (&b"POST /0 HTTP/1.1\r\nContent-Length: x\r\n\r\n"[..], 400),
+            (&b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"[..], 431),
+            (&b"GET / HTTP/1.1\r\nA: 01234567890123456789012345678901234567890123456789\r\n\r\n"[..], 431),
+            (&b"GET / HTTP/1.1\r\n"[..], 408),
+            (&b"POST /0 HTTP/1.1\r\nContent-Length: 4\r\n\r\nbo"[..], 408),
+            (&b"GET  HTTP/1.1\r\n\r\n"[..], 400),
+            (&b"POST /0 HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n"[..], 413),
+            (&b"POST /0 HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n"[..], 413),
+        ];
+        for (raw, code) in codes {
+            match read_raw(raw, &limits) {
+                Ok(_) => panic!("Request should have been rejected"),
+                Err(value) => assert_eq!(value.to_code(), code),
+            }
+        }
+    }
+
+    #[test]
+    fn test_request_parsing() {
+        let request = read_request("POST /0/1 HTTP/1.1\r\ncontent-length: 5\r\nX-Lease-Token:42 \r\n\r\nhello").ok().unwrap();
+        assert!(request.method == HTTPMethod::POST);
+        assert_eq!(request.uri, "0/1");
+        assert_eq!(request.body, b"hello");
+        assert_eq!(request.header("Content-Length").unwrap(), "5");
+        assert_eq!(request.header("x-lease-token").unwrap(), "42");
+
+        // Bare line feeds, leading blank lines, absolute targets and queries are tolerated
+        let request = read_request("\r\nGET http://example.com:7878/batch/4?x=1 HTTP/1.0\n\n").ok().unwrap();
+        assert_eq!(request.uri, "batch/4");
+        assert_eq!(request.version, "HTTP/1.0");
+
+        // Multi-byte characters survive the body
+        let request = read_request("POST /0 HTTP/1.1\r\nContent-Length: 6\r\n\r\nh\u{e9}llo").ok().unwrap();
+        assert_eq!(request.text(), Some("h\u{e9}llo"));
+
+        // Bodies need not be text at all
+        let request = HTTPRequest::read(&b"POST /0 HTTP/1.1\r\nContent-Length: 3\r\n\r\n\x00\xff\n"[..]).ok().unwrap();
+        assert_eq!(request.body, b"\x00\xff\n");
+        assert!(request.text().is_none());
+
+        let request = read_request("POST /0 HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4;ext=1\r\nWiki\r\na\r\npedia in c\r\n0\r\nTrailer: x\r\n\r\n").ok().unwrap();
+        assert_eq!(request.body, b"Wikipedia in c");
+    }
+
+    #[test]
+    fn test_request_errors() {
+        let codes = [
+            ("", 400),
+            ("GET / HTTP/1.1", 400),
+            ("PUT / HTTP/1.1\r\n\r\n", 405),
+            ("GET / HTTP/2\r\n\r\n", 505),
+            ("GET / FTP/1.1\r\n\r\n", 400),
+            ("GET relative HTTP/1.1\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nNoColon\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nBad Name: 1\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nA: 1\r\na: 2\r\n\r\n", 400),
+            ("POST /0 HTTP/1.1\r\nContent-Length: -1\r\n\r\n", 400),
+            ("POST /0 HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort", 400),
+            ("POST /0 HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n", 400),
+            ("POST /0 HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 1\r\n\r\n1\r\na\r\n0\r\n\r\n", 400),
+        ];
+        for (raw, code) in codes {
+            match read_request(raw) {
+                Ok(_) => panic!("Request {:?} should have been rejected", raw),
+                Err(value) => assert_eq!(value.to_code(), code, "{:?}", raw),
+            }
+        }
+    }
+
+    #[test]
+    fn test_request_serialization() {
+        let mut request = HTTPRequest::new(HTTPMethod::POST, "0/1".to_string());
+        request.body = b"res\xffult".to_vec();
+        assert_eq!(request.to_bytes(), b"POST /0/1 HTTP/1.1\r\nContent-Length: 7\r\n\r\nres\xffult");
+        let parsed = HTTPRequest::read(&request.to_bytes()[..]).ok().unwrap();
+        assert_eq!(parsed.body, b"res\xffult");
+
+        let request = HTTPRequest::new(HTTPMethod::GET, "".to_string());
+        assert_eq!(request.to_bytes(), b"GET / HTTP/1.1\r\n\r\n");
+    }
+
+    #[test]
+    fn test_response_serialization() {
+        let mut response = HTTPResponse::new(HTTPResponseCode::OK);
+        response.headers.insert("content-length".to_string(), "99".to_string());
+        response.content = "h\u{e9}".to_string().into_bytes();
+        assert_eq!(response.as_bytes(), "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nh\u{e9}".as_bytes());
+        assert_eq!(HTTPResponse::new(HTTPResponseCode::NotFound).as_bytes(), b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(HTTPResponse::new(HTTPResponseCode::NoContent).as_bytes(), b"HTTP/1.1 204 No Content\r\n\r\n");
+    }
+
+    #[test]
+    fn test_response_parsing() {
+        let response = HTTPResponse::read("HTTP/1.1 200 OK\r\ncontent-length: 2\r\nretry-after: 1\r\n\r\nok".as_bytes()).ok().unwrap();
+        assert!(response.status == HTTPResponseCode::OK);
+        assert_eq!(response.text(), Some("ok"));
+        assert_eq!(response.header("Retry-After").unwrap(), "1");
+
+        // Without a length the body runs to the end of the stream
+        let response = HTTPResponse::read("HTTP/1.0 200\r\n\r\nuntil close".as_bytes()).ok().unwrap();
+        assert_eq!(response.content, b"until close");
+
+        let response = HTTPResponse::read("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n".as_bytes()).ok().unwrap();
+        assert_eq!(response.content, b"ok");
+
+        let response = HTTPResponse::read("HTTP/1.1 204 No Content\r\n\r\n".as_bytes()).ok().unwrap();
+        assert!(response.status == HTTPResponseCode::NoContent);
+
+        // Codes without a variant keep their number
+        let response = HTTPResponse::read("HTTP/1.1 502 Bad Gateway\r\n\r\n".as_bytes()).ok().unwrap();
+        assert!(response.status == HTTPResponseCode::Other(502));
+        assert!(HTTPResponse::new(HTTPResponseCode::Other(301)).as_bytes().starts_with(b"HTTP/1.1 301 Redirection\r\n"));
+        assert!(HTTPResponse::read("HTTP/1.1 999 Odd\r\n\r\n".as_bytes()).is_err());
+        assert!(HTTPResponse::read("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nok".as_bytes()).is_err());
+        assert!(HTTPResponse::read("garbage".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        assert!(read_request("GET / HTTP/1.1\r\n\r\n").ok().unwrap().keeps_alive());
+        assert!(!read_request("GET / HTTP/1.1\r\nConnection: Close\r\n\r\n").ok().unwrap().keeps_alive());
+        assert!(!read_request("GET / HTTP/1.0\r\n\r\n").ok().unwrap().keeps_alive());
+        assert!(read_request("GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").ok().unwrap().keeps_alive());
+
+        // Back-to-back messages on one stream are read one at a time
+        let mut reader = "HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\naHTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".as_bytes();
+        let response = HTTPResponse::read(&mut reader).ok().unwrap();
+        assert_eq!(response.content, b"a");
+        assert!(response.keeps_alive());
+        let response = HTTPResponse::read(&mut reader).ok().unwrap();
+        assert!(response.status == HTTPResponseCode::NoContent);
+        assert!(!response.keeps_alive());
+    }
+}
@@ -1,4 +1,6 @@
-use std::{collections::{HashMap, HashSet}, time::SystemTime, vec};
+use std::{cmp::min, collections::{BTreeMap, HashMap, HashSet, VecDeque}, fs, hash::{Hash, Hasher}, io::{self, Write}, ops::Range, path::PathBuf, time::{Duration, SystemTime}, vec};
+
+use crate::{journal::Journal, json, param::{Parameter, Value}};
 
 #[derive(Debug)]
 pub enum Error {
@@ -7,22 +9,31 @@ pub enum Error {
     OutOfBounds,
     UnexpectedString,
     JobNotFound,
+    AlreadyCompleted,
+    StaleLease,
+    Journal(io::Error),
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct JobDimension {
     pub index: usize,
     pub span: usize,
+    pub length: usize,
 }
 
 impl JobDimension {
     pub fn new(span: usize) -> Result<Self, Error> {
-        if span == 0 {
+        return Self::chunked(span, 1);
+    }
+
+    pub fn chunked(span: usize, length: usize) -> Result<Self, Error> {
+        if span == 0 || length == 0 {
             return Err(Error::ZeroSizedDimension);
         }
         return Ok(Self {
             index: 0,
             span,
+            length,
         });
     }
 
@@ -31,26 +42,51 @@ impl JobDimension {
         if split.is_none() {
             return Err(Error::UnexpectedString);
         } else {
-            let (index_str, span_str) = split.unwrap();
-            let index = match index_str.parse::<usize>() {
-                Ok(value) => value,
-                Err(_) => return Err(Error::UnexpectedString)
-            };
+            let (range_str, span_str) = split.unwrap();
+            let (index, length) = Self::parse_range(range_str)?;
             let span = match span_str.parse::<usize>() {
                 Ok(value) => value,
                 Err(_) => return Err(Error::UnexpectedString)
             };
-            if index >= span {
+            if index + length > span {
                 return Err(Error::OutOfBounds);
             } else {
                 return Ok(Self {
                     index,
                     span,
+                    length,
                 });
             }
         }
     }
 
+    fn parse_range(input: &str) -> Result<(usize, usize), Error> {
+        // Either a single index or an exclusive range "start..end"
+        let (start_str, end_str) = match input.split_once("..") {
+            Some(value) => value,
+            None => (input, ""),
+        };
+        let start = match start_str.parse::<usize>() {
+            Ok(value) => value,
+            Err(_) => return Err(Error::UnexpectedString)
+        };
+        if end_str.len() == 0 {
+            return Ok((start, 1));
+        }
+        let end = match end_str.parse::<usize>() {
+            Ok(value) => value,
+            Err(_) => return Err(Error::UnexpectedString)
+        };
+        if end <= start {
+            return Err(Error::UnexpectedString);
+        }
+        return Ok((start, end - start));
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        return self.index..self.index + self.length;
+    }
+
     pub fn has_job(&self) -> bool {
         return self.index < self.span;
     }
@@ -65,10 +101,10 @@ impl JobDimension {
 
     pub fn bounds(&self) -> (f64, f64) {
         let lower = self.index as f64 / self.span as f64;
-        let upper = if self.index + 1 == self.span {
+        let upper = if self.index + self.length >= self.span {
             1.0
         } else {
-            (self.index + 1) as f64 / self.span as f64
+            (self.index + self.length) as f64 / self.span as f64
         };
         return (lower, upper);
     }
@@ -78,8 +114,15 @@ impl JobDimension {
         return (lower + upper) / 2.0;
     }
 
+    pub fn to_uri(&self) -> String {
+        if self.length == 1 {
+            return self.index.to_string();
+        }
+        return format!("{}..{}", self.index, self.index + self.length);
+    }
+
     pub fn to_string(&self) -> String {
-        return format!("{}/{}",self.index, self.span);
+        return format!("{}/{}",self.to_uri(), self.span);
     }
 }
 
@@ -88,6 +131,7 @@ impl Default for JobDimension {
         return Self {
             index: 0,
             span: 1,
+            length: 1,
         }
     }
 }
@@ -97,7 +141,7 @@ impl std::iter::Iterator for JobDimension {
     fn next(&mut self) -> Option<Self::Item> {
         if self.has_job() {
             let result = self.index;
-            self.index += 1;
+            self.index += self.length;
             return Some(result);
         } else {
             return None;
@@ -105,9 +149,26 @@ impl std::iter::Iterator for JobDimension {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Hash)]
+#[derive(Clone)]
 pub struct Job {
     index: Vec<JobDimension>,
+    parameters: Vec<Parameter>,
+    pub lease: Option<u64>,
+}
+
+// Parameters and leases are not part of a job's identity, only the index is
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        return self.index.eq(&other.index);
+    }
+}
+
+impl Eq for Job {}
+
+impl Hash for Job {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
 }
 
 impl Job {
@@ -126,10 +187,39 @@ impl Job {
             result.push(JobDimension {
                 index: index.get(i).unwrap().clone(),
                 span: dimensions.get(i).unwrap().clone(),
+                length: 1,
+            });
+        }
+        return Ok(Self {
+            index: result,
+            parameters: Vec::new(),
+            lease: None,
+        });
+    }
+
+    pub fn from_ranges(ranges: &Vec<Range<usize>>, dimensions: &Vec<usize>) -> Result<Self, Error> {
+        if ranges.len() != dimensions.len() {
+            return Err(Error::DimensionMismatch);
+        }
+
+        let mut result = Vec::with_capacity(dimensions.len());
+        for (range, span) in ranges.iter().zip(dimensions) {
+            if range.start >= range.end {
+                return Err(Error::ZeroSizedDimension);
+            }
+            if range.end > *span {
+                return Err(Error::OutOfBounds);
+            }
+            result.push(JobDimension {
+                index: range.start,
+                span: *span,
+                length: range.end - range.start,
             });
         }
         return Ok(Self {
-            index: result
+            index: result,
+            parameters: Vec::new(),
+            lease: None,
         });
     }
 
@@ -139,18 +229,47 @@ impl Job {
             return  Err(Error::ZeroSizedDimension);
         }
         let mut result = Vec::with_capacity(rows.len());
+        let mut parameters = Vec::new();
+        let mut lease = None;
         for row in rows {
             if row.len() == 0 {
                 continue;
             }
-            let dimension = JobDimension::parse(&row.to_string())?;
+            if let Some(lease_str) = row.strip_prefix("Lease: ") {
+                match lease_str.parse::<u64>() {
+                    Ok(value) => lease = Some(value),
+                    Err(_) => return Err(Error::UnexpectedString),
+                }
+                continue;
+            }
+            // A row is "<range>/<span>" optionally followed by " <name>=<values>"
+            let (dimension_str, parameter_str) = row.split_once(' ').unwrap_or((row, ""));
+            let dimension = JobDimension::parse(&dimension_str.to_string())?;
             result.push(dimension);
+            if parameter_str.len() > 0 {
+                parameters.push(Parameter::parse(parameter_str)?);
+            }
+        }
+        if parameters.len() > 0 && parameters.len() != result.len() {
+            return Err(Error::DimensionMismatch);
         }
         return Ok(Self {
-            index: result
+            index: result,
+            parameters,
+            lease,
         });
     }
 
+    pub fn from_uri(uri: &String, dimensions: &Vec<usize>) -> Result<Self, Error> {
+        let parts = uri.split('/');
+        let mut ranges: Vec<Range<usize>> = Vec::with_capacity(dimensions.len());
+        for part in parts {
+            let (index, length) = JobDimension::parse_range(part)?;
+            ranges.push(index..index + length);
+        }
+        return Self::from_ranges(&ranges, dimensions);
+    }
+
     pub fn order(&self) -> usize {
         return self.index.len();
     }
@@ -174,10 +293,74 @@ impl Job {
         return result;
     }
 
+    pub fn parameters(&self) -> &Vec<Parameter> {
+        return &self.parameters;
+    }
+
+    pub fn param(&self, name: &str) -> Option<Value> {
+        let position = self.parameters.iter().position(|parameter| parameter.name == name)?;
+        return self.param_at(name, self.index.get(position)?.index);
+    }
+
+    pub fn param_at(&self, name: &str, index: usize) -> Option<Value> {
+        let position = self.parameters.iter().position(|parameter| parameter.name == name)?;
+        let slice = self.index.get(position)?;
+        if !slice.range().contains(&index) {
+            return None;
+        }
+        return self.parameters.get(position)?.value(index, slice.span);
+    }
+
+    pub fn size(&self) -> usize {
+        let mut result = 1;
+        for slice in &self.index {
+            result *= slice.length;
+        }
+        return result;
+    }
+
+    pub fn linear_index(&self, point: &Vec<usize>) -> usize {
+        assert!(point.len() == self.order());
+        let mut result = 0;
+        for (index, slice) in point.iter().zip(&self.index) {
+            result = result * slice.span + index;
+        }
+        return result;
+    }
+
+    pub fn linear_runs(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        // Trailing dimensions covered in full join consecutive rows into a single run
+        let mut depth = self.index.len() - 1;
+        while depth > 0 && self.index[depth].index == 0 && self.index[depth].length == self.index[depth].span {
+            depth -= 1;
+        }
+        let inner: usize = self.index[depth + 1..].iter().map(|slice| slice.span).product();
+        let length = self.index[depth].length * inner;
+        let outer = JobIndices {
+            ranges: self.index[..depth].iter().map(|slice| slice.range()).collect(),
+            next: Some(self.index[..depth].iter().map(|slice| slice.index).collect()),
+        };
+        return outer.map(move |point| {
+            let mut start = 0;
+            for (index, slice) in point.iter().chain([self.index[depth].index].iter()).zip(&self.index) {
+                start = start * slice.span + index;
+            }
+            let start = start * inner;
+            return start..start + length;
+        });
+    }
+
+    pub fn indices(&self) -> JobIndices {
+        return JobIndices {
+            ranges: self.index.iter().map(|slice| slice.range()).collect(),
+            next: Some(self.index.iter().map(|slice| slice.index).collect()),
+        };
+    }
+
     pub fn to_uri(&self) -> String {
         let mut result = String::new();
         for dimension in &self.index {
-            result.push_str(&dimension.index.to_string());
+            result.push_str(&dimension.to_uri());
             result.push('/');
         }
         result.pop();
@@ -186,32 +369,373 @@ impl Job {
 
     pub fn to_string(&self) -> String {
         let mut result = String::new();
-        for slice in &self.index {
-            result.push_str(&format!("{}\r\n",slice.to_string()));
+        if let Some(lease) = self.lease {
+            result.push_str(&format!("Lease: {lease}\r\n"));
+        }
+        for (i, slice) in self.index.iter().enumerate() {
+            result.push_str(&slice.to_string());
+            if let Some(parameter) = self.parameters.get(i) {
+                result.push(' ');
+                result.push_str(&parameter.to_string());
+            }
+            result.push_str("\r\n");
+        }
+        return result;
+    }
+
+    pub fn parse_list(input: &String) -> Result<Vec<Self>, Error> {
+        let mut result = Vec::new();
+        for block in input.split("\r\n\r\n") {
+            if block.len() == 0 {
+                continue;
+            }
+            result.push(Self::parse(&block.to_string())?);
+        }
+        return Ok(result);
+    }
+
+    pub fn list_to_string(jobs: &Vec<Job>) -> String {
+        let mut blocks = Vec::with_capacity(jobs.len());
+        for job in jobs {
+            blocks.push(job.to_string());
+        }
+        return blocks.join("\r\n");
+    }
+}
+
+pub struct JobIndices {
+    ranges: Vec<Range<usize>>,
+    next: Option<Vec<usize>>,
+}
+
+impl std::iter::Iterator for JobIndices {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next.take()?;
+
+        // Advance the last dimension first and carry into earlier ones
+        let mut next = result.clone();
+        for i in (0..next.len()).rev() {
+            next[i] += 1;
+            if next[i] < self.ranges[i].end {
+                self.next = Some(next);
+                break;
+            }
+            next[i] = self.ranges[i].start;
+        }
+        return Some(result);
+    }
+}
+
+// Header that carries the lease token back with a result or failure
+pub const LEASE_HEADER: &str = "X-Lease-Token";
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct JobResult {
+    pub uri: String,
+    pub body: Vec<u8>,
+    pub lease: Option<u64>,
+}
+
+impl JobResult {
+    pub fn new(job: &Job, body: Vec<u8>) -> Self {
+        return Self {
+            uri: job.to_uri(),
+            body,
+            lease: job.lease,
+        };
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = match self.lease {
+            Some(lease) => format!("{} {} {}\r\n", self.uri, self.body.len(), lease),
+            None => format!("{} {}\r\n", self.uri, self.body.len()),
+        };
+        let mut result = header.into_bytes();
+        result.extend_from_slice(&self.body);
+        result.extend_from_slice(b"\r\n");
+        return result;
+    }
+
+    pub fn parse_list(input: &[u8]) -> Result<Vec<Self>, Error> {
+        let mut result = Vec::new();
+        let mut rem = input;
+        while rem.len() > 0 {
+            // Each result is framed as "<uri> <length> [lease]\r\n<body>\r\n" and only the header is text
+            let end = match rem.windows(2).position(|window| window == b"\r\n") {
+                Some(value) => value,
+                None => return Err(Error::UnexpectedString),
+            };
+            let header = match std::str::from_utf8(&rem[..end]) {
+                Ok(value) => value,
+                Err(_) => return Err(Error::UnexpectedString),
+            };
+            let tail = &rem[end + 2..];
+            let (uri, rem_header) = match header.split_once(' ') {
+                Some(value) => value,
+                None => return Err(Error::UnexpectedString),
+            };
+            let (length_str, lease_str) = rem_header.split_once(' ').unwrap_or((rem_header, ""));
+            let lease = if lease_str.len() > 0 {
+                match lease_str.parse::<u64>() {
+                    Ok(value) => Some(value),
+                    Err(_) => return Err(Error::UnexpectedString),
+                }
+            } else {
+                None
+            };
+            let length = match length_str.parse::<usize>() {
+                Ok(value) => value,
+                Err(_) => return Err(Error::UnexpectedString),
+            };
+            let body = match tail.get(..length) {
+                Some(value) => value,
+                None => return Err(Error::UnexpectedString),
+            };
+            rem = match tail.get(length..).and_then(|value| value.strip_prefix(b"\r\n")) {
+                Some(value) => value,
+                None => return Err(Error::UnexpectedString),
+            };
+            result.push(Self {
+                uri: uri.to_string(),
+                body: body.to_vec(),
+                lease,
+            });
+        }
+        return Ok(result);
+    }
+
+    pub fn list_to_bytes(results: &Vec<JobResult>) -> Vec<u8> {
+        let mut result = Vec::new();
+        for entry in results {
+            result.extend_from_slice(&entry.to_bytes());
         }
         return result;
     }
 }
 
+// Sorted, disjoint runs of linear indices, so finished blocks cost the same however large they are
+#[derive(Clone, Debug)]
+struct RangeSet {
+    runs: BTreeMap<usize, usize>,
+    size: usize,
+    count: usize,
+}
+
+impl RangeSet {
+    fn new(size: usize) -> Self {
+        return Self {
+            runs: BTreeMap::new(),
+            size,
+            count: 0,
+        };
+    }
+
+    fn insert(&mut self, range: Range<usize>) -> usize {
+        assert!(range.end <= self.size);
+        if range.is_empty() {
+            return 0;
+        }
+
+        // Merge every run that overlaps or touches the new one
+        let (mut start, mut end) = (range.start, range.end);
+        let mut covered = 0;
+        let touching: Vec<(usize, usize)> = self.runs.range(..=range.end).rev()
+            .take_while(|(_, run_end)| **run_end >= range.start)
+            .map(|(run_start, run_end)| (*run_start, *run_end))
+            .collect();
+        for (run_start, run_end) in touching {
+            covered += min(run_end, range.end).saturating_sub(std::cmp::max(run_start, range.start));
+            start = min(start, run_start);
+            end = std::cmp::max(end, run_end);
+            self.runs.remove(&run_start);
+        }
+        self.runs.insert(start, end);
+        let added = range.len() - covered;
+        self.count += added;
+        return added;
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        return self.contains_range(index..index + 1);
+    }
+
+    fn contains_range(&self, range: Range<usize>) -> bool {
+        if range.is_empty() {
+            return true;
+        }
+        return match self.runs.range(..=range.start).next_back() {
+            Some((_, end)) => *end >= range.end,
+            None => false,
+        };
+    }
+
+    fn len(&self) -> usize {
+        return self.count;
+    }
+
+    fn runs(&self) -> Vec<Range<usize>> {
+        return self.runs.iter().map(|(start, end)| *start..*end).collect();
+    }
+}
+
+// Number of points in a job space, which must fit the linear index
+fn space_size(dimensions: &Vec<usize>) -> Result<usize, Error> {
+    let mut result: usize = 1;
+    for span in dimensions {
+        result = match result.checked_mul(*span) {
+            Some(value) => value,
+            None => return Err(Error::OutOfBounds),
+        };
+    }
+    return Ok(result);
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PointState {
+    Available,
+    Pending,
+    Done,
+    Failed,
+    Unlisted,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LeasePolicy {
+    Current,
+    FirstWins,
+    LastWins,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Schedule {
+    Static,
+    // Each job covers the remaining points divided by the worker count, and never less than min_chunk
+    Guided {
+        min_chunk: usize,
+        workers: usize,
+    },
+}
+
 #[derive(PartialEq, Eq, Clone, Hash)]
 pub struct JobStack {
     top: Job,
+    schedule: Schedule,
+    remaining: usize,
+    list: Option<VecDeque<Job>>,
 }
 
 impl JobStack {
     pub fn new(dimensions: &Vec<usize>) -> Result<JobStack, Error> {
         return Ok(
             Self {
-                top: Job::new(&vec![0; dimensions.len()], dimensions)?
+                top: Job::new(&vec![0; dimensions.len()], dimensions)?,
+                schedule: Schedule::Static,
+                remaining: space_size(dimensions)?,
+                list: None,
             }
         );
     }
 
+    pub fn from_jobs(dimensions: &Vec<usize>, jobs: Vec<Job>) -> Result<JobStack, Error> {
+        space_size(dimensions)?;
+        let mut seen = HashSet::new();
+        let mut list = VecDeque::with_capacity(jobs.len());
+        let mut remaining = 0;
+        for job in jobs {
+            if job.dimensions().ne(dimensions) {
+                return Err(Error::DimensionMismatch);
+            }
+            // Drop repeated entries so a job is never pending twice
+            if seen.insert(job.clone()) {
+                remaining += job.size();
+                list.push_back(job);
+            }
+        }
+        return Ok(Self {
+            top: Job::new(&vec![0; dimensions.len()], dimensions)?,
+            schedule: Schedule::Static,
+            remaining,
+            list: Some(list),
+        });
+    }
+
+    pub fn chunked(dimensions: &Vec<usize>, chunks: &Vec<usize>) -> Result<JobStack, Error> {
+        if chunks.len() != dimensions.len() {
+            return Err(Error::DimensionMismatch);
+        }
+        let mut top = Job::new(&vec![0; dimensions.len()], dimensions)?;
+        for (slice, chunk) in top.index.iter_mut().zip(chunks) {
+            if *chunk == 0 {
+                return Err(Error::ZeroSizedDimension);
+            }
+            slice.length = *chunk;
+        }
+        return Ok(Self {
+            top,
+            schedule: Schedule::Static,
+            remaining: space_size(dimensions)?,
+            list: None,
+        });
+    }
+
+    pub fn with_schedule(&mut self, schedule: Schedule) -> &mut Self {
+        if let Schedule::Guided { min_chunk, workers } = schedule {
+            assert!(min_chunk > 0 && workers > 0);
+        }
+        self.schedule = schedule;
+        self
+    }
+
+    pub fn remaining(&self) -> usize {
+        return self.remaining;
+    }
+
+    fn guided_job(&mut self, min_chunk: usize, workers: usize) -> Job {
+        // Hand out a share of the remaining work as the widest block that fits,
+        // spanning whole rows of the inner dimensions once the cursor is at a row start
+        let target = std::cmp::max(min_chunk, self.remaining.div_ceil(workers));
+        let count = self.top.index.len();
+        let mut inner = vec![1; count];
+        for i in (0..count - 1).rev() {
+            inner[i] = inner[i + 1] * self.top.index[i + 1].span;
+        }
+        let mut depth = count - 1;
+        for i in (0..count - 1).rev() {
+            if self.top.index[i + 1].index != 0 || inner[i] > target {
+                break;
+            }
+            depth = i;
+        }
+        let mut result = self.top.clone();
+        for (i, slice) in result.index.iter_mut().enumerate() {
+            slice.length = match i.cmp(&depth) {
+                std::cmp::Ordering::Less => 1,
+                std::cmp::Ordering::Equal => min(target / inner[depth], slice.span - slice.index),
+                std::cmp::Ordering::Greater => slice.span,
+            };
+        }
+
+        // Step past the block and carry into the outer dimensions
+        self.top.index[depth].index += result.index[depth].length;
+        for i in (1..=depth).rev() {
+            if self.top.index[i].is_finished() {
+                self.top.index[i].reset();
+                self.top.index[i - 1].index += 1;
+            }
+        }
+        return result;
+    }
+
     pub fn order(&self) -> usize {
         return self.top.order();
     }
 
     pub fn is_empty(&self) -> bool {
+        if let Some(list) = &self.list {
+            return list.is_empty();
+        }
         assert!(!self.top.index.is_empty());
         return self.top.index.first().unwrap().is_finished();
     }
@@ -221,11 +745,27 @@ impl std::iter::Iterator for JobStack {
     type Item = Job;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(list) = self.list.as_mut() {
+            let result = list.pop_front()?;
+            self.remaining -= result.size();
+            return Some(result);
+        }
         if self.top.index.first()?.is_finished() {
             return None
         } 
         assert!(self.top.index.last()?.has_job());
-        let result = self.top.clone();
+        if let Schedule::Guided { min_chunk, workers } = self.schedule {
+            let result = self.guided_job(min_chunk, workers);
+            self.remaining -= result.size();
+            return Some(result);
+        }
+        let mut result = self.top.clone();
+
+        // Trim the trailing chunk of each dimension to the span
+        for slice in result.index.iter_mut() {
+            slice.length = min(slice.length, slice.span - slice.index);
+        }
+        self.remaining -= result.size();
         self.top.index.last_mut()?.next();
         loop {
             let mut repeat = false;
@@ -249,77 +789,793 @@ pub struct JobManager {
     stack: JobStack,
     pending: HashMap<Job, SystemTime>,
     abandoned: HashSet<Job>,
+    failed: HashSet<Job>,
+    completed: RangeSet,
+    attempts: HashMap<Job, usize>,
+    reasons: HashMap<Job, String>,
+    leases: HashMap<Job, u64>,
+    copies: HashMap<Job, Vec<u64>>,
+    failed_copies: HashSet<Job>,
+    dispatched: HashMap<Job, SystemTime>,
+    next_lease: u64,
+    lease_policy: LeasePolicy,
+    parameters: Vec<Parameter>,
+    lease: Option<Duration>,
+    max_attempts: Option<usize>,
+    speculation: Option<usize>,
+    dispatches: usize,
+    completions: usize,
+    abandons: usize,
+    expiries: usize,
+    checkpoint: Option<(PathBuf, Duration)>,
+    last_checkpoint: SystemTime,
+    journal: Option<Journal>,
+    journal_error: Option<io::Error>,
+    sequence: u64,
 }
 
 impl JobManager {
     pub fn new(dimensions: &Vec<usize>) -> Result<Self, Error> {
-        return Ok(Self {
-            stack: JobStack::new(dimensions)?,
-            pending: HashMap::new(),
-            abandoned: HashSet::new(),
-        });
+        return Ok(Self::from_stack(JobStack::new(dimensions)?));
     }
 
-    fn set_pending(&mut self, job: &Job) {
-        assert!(!self.pending.contains_key(job));
-        self.pending.insert(job.clone(), SystemTime::now());
+    pub fn chunked(dimensions: &Vec<usize>, chunks: &Vec<usize>) -> Result<Self, Error> {
+        return Ok(Self::from_stack(JobStack::chunked(dimensions, chunks)?));
     }
 
-    pub fn jobs_pending(&self) -> HashMap<Job, SystemTime> {
-        return self.pending.clone();
+    pub fn from_jobs(dimensions: &Vec<usize>, jobs: Vec<Job>) -> Result<Self, Error> {
+        return Ok(Self::from_stack(JobStack::from_jobs(dimensions, jobs)?));
     }
 
-    pub fn jobs_abandonded(&self) -> HashSet<Job> {
-        return self.abandoned.clone();
+    pub fn with_schedule(&mut self, schedule: Schedule) -> &mut Self {
+        self.stack.with_schedule(schedule);
+        self
     }
 
-    pub fn from_uri(&self, uri: String) -> Result<Job, Error> {
-        let parts = uri.split('/');
-        let mut index: Vec<usize> = Vec::with_capacity(self.stack.order());
-        for part in parts {
-            match part.parse::<usize>() {
-                Ok(value) => index.push(value),
-                Err(_) => return Err(Error::UnexpectedString),
+    pub fn with_parameters(&mut self, parameters: Vec<Parameter>) -> Result<&mut Self, Error> {
+        let dimensions = self.stack.top.dimensions();
+        if parameters.len() != dimensions.len() {
+            return Err(Error::DimensionMismatch);
+        }
+        for (parameter, span) in parameters.iter().zip(&dimensions) {
+            if parameter.value(span - 1, *span).is_none() {
+                return Err(Error::OutOfBounds);
             }
         }
-        return Job::new(&index, &self.stack.top.dimensions());
+        self.parameters = parameters;
+        return Ok(self);
     }
 
-    pub fn pop(&mut self) -> Option<Job> {
-        if !self.abandoned.is_empty() {
-            let result = self.abandoned.iter().next().cloned().unwrap();
-            self.abandoned.remove(&result);
-            self.set_pending(&result);
-            return Some(result);
-        } else if !self.stack.is_empty() {
-            let result = self.stack.next()?;
-            assert!(!self.pending.contains_key(&result));
-            self.set_pending(&result);
-            assert!(self.pending.contains_key(&result));
-            return Some(result);
-        } else {
-            return None;
+    pub fn with_checkpoint(&mut self, path: &PathBuf, interval: Duration) -> &mut Self {
+        self.checkpoint = Some((path.clone(), interval));
+        self
+    }
+
+    pub fn checkpoint(&mut self) -> Result<(), io::Error> {
+        let path = match &self.checkpoint {
+            Some((path, _)) => path.clone(),
+            None => return Ok(()),
+        };
+
+        // Write to a sibling file first so a crash never leaves a torn checkpoint
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(self.to_checkpoint().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)?;
+
+        // The rename must be durable before the journal that backs it goes away
+        #[cfg(unix)]
+        {
+            let parent = match path.parent() {
+                Some(value) if value.as_os_str().len() > 0 => value.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            fs::File::open(parent)?.sync_all()?;
+        }
+        self.last_checkpoint = SystemTime::now();
+
+        // The checkpoint now holds every journal entry up to the current sequence
+        if let Some(journal) = self.journal.as_mut() {
+            journal.truncate()?;
         }
+        self.journal_error = None;
+        return Ok(());
     }
 
-    pub fn complete(&mut self, uri: String) -> Result<Job, Error> {
-        let job = self.from_uri(uri)?;
-        if self.pending.contains_key(&job) {
+    pub fn with_journal(&mut self, path: &PathBuf) -> Result<&mut Self, io::Error> {
+        // Only a checkpoint truncates the journal or clears a write error
+        if self.checkpoint.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A journal needs a checkpoint"));
+        }
+        // Entries already folded into the checkpoint are skipped
+        for entry in Journal::read(path)? {
+            if let Err(err) = self.replay(&entry) {
+                let message = format!("Could not replay journal entry \"{entry}\": {err:?}");
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+        self.journal = Some(Journal::open(path)?);
+
+        // Jobs that were pending before the restart are handed out again
+        let pending: Vec<Job> = self.pending.keys().cloned().collect();
+        for job in pending {
             self.pending.remove(&job);
-            return Ok(job);
-        } else if self.abandoned.contains(&job) {
-            self.abandoned.remove(&job);
-            return Ok(job);
-        } else {
-            return Err(Error::JobNotFound);
+            self.dispatched.remove(&job);
+            self.leases.remove(&job);
+            self.abandoned.insert(job.clone());
+            self.record(format!("release {}", job.to_uri()))?;
         }
+        return Ok(self);
     }
 
-    pub fn abandon(&mut self, job: &Job) {
-        assert!(self.pending.contains_key(&job));
-        assert!(!self.abandoned.contains(&job));
+    fn record(&mut self, entry: String) -> Result<(), io::Error> {
+        // Once an entry is lost the journal is only trusted again after the next checkpoint
+        if let Some(err) = &self.journal_error {
+            return Err(io::Error::new(err.kind(), err.to_string()));
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(err) = journal.append(&format!("{} {}", self.sequence + 1, entry)) {
+                self.journal_error = Some(io::Error::new(err.kind(), err.to_string()));
+                return Err(err);
+            }
+            self.sequence += 1;
+        }
+        return Ok(());
+    }
+
+    pub fn journal_error(&self) -> Option<&io::Error> {
+        return self.journal_error.as_ref();
+    }
+
+    fn replay(&mut self, entry: &String) -> Result<(), Error> {
+        let mut parts = entry.splitn(4, ' ');
+        let sequence = match parts.next().unwrap().parse::<u64>() {
+            Ok(value) => value,
+            Err(_) => return Err(Error::UnexpectedString),
+        };
+        if sequence <= self.sequence {
+            return Ok(());
+        }
+        let kind = parts.next().unwrap_or("");
+        let job = self.from_uri(parts.next().unwrap_or("").to_string())?;
+        match kind {
+            "pop" => {
+                if !self.abandoned.remove(&job) {
+                    // The stack hands out jobs in a fixed order for a given configuration
+                    match self.stack.next() {
+                        Some(next) if next.eq(&job) => (),
+                        _ => return Err(Error::JobNotFound),
+                    }
+                }
+                self.set_pending(&job);
+            }
+            "complete" => {
+                self.complete(job.to_uri())?;
+            }
+            "abandon" => {
+                self.reclaim(&job);
+                if !self.pending.contains_key(&job) {
+                    return Err(Error::JobNotFound);
+                }
+                if let Err(err) = self.abandon(&job) {
+                    return Err(Error::Journal(err));
+                }
+            }
+            "fail" => {
+                let reason = match parts.next().and_then(json::unescape) {
+                    Some(value) => value,
+                    None => return Err(Error::UnexpectedString),
+                };
+                self.reclaim(&job);
+                self.fail(job.to_uri(), reason)?;
+            }
+            "release" => {
+                if self.pending.remove(&job).is_none() {
+                    return Err(Error::JobNotFound);
+                }
+                self.dispatched.remove(&job);
+                self.leases.remove(&job);
+                self.abandoned.insert(job);
+            }
+            &_ => return Err(Error::UnexpectedString),
+        }
+        self.sequence = sequence;
+        return Ok(());
+    }
+
+    // A checkpoint stores running jobs as available, so a job that was running when it was
+    // taken and later abandoned or failed comes back abandoned rather than pending
+    fn reclaim(&mut self, job: &Job) {
+        if self.abandoned.remove(job) {
+            self.pending.insert(job.clone(), SystemTime::now());
+        }
+    }
+
+    pub fn checkpoint_if_due(&mut self) -> Result<(), io::Error> {
+        let interval = match &self.checkpoint {
+            Some((_, interval)) => *interval,
+            None => return Ok(()),
+        };
+        let elapsed = SystemTime::now().duration_since(self.last_checkpoint).unwrap_or(Duration::ZERO);
+        if elapsed < interval {
+            return Ok(());
+        }
+        return self.checkpoint();
+    }
+
+    pub fn to_checkpoint(&self) -> String {
+        let mut result = "netspatch-checkpoint 1\n".to_string();
+        let spans: Vec<String> = self.stack.top.index.iter().map(|slice| slice.span.to_string()).collect();
+        result.push_str(&format!("dimensions {}\n", spans.join(" ")));
+        let cursor: Vec<String> = self.stack.top.index.iter().map(|slice| format!("{}+{}", slice.index, slice.length)).collect();
+        result.push_str(&format!("cursor {}\n", cursor.join(" ")));
+        result.push_str(&format!("remaining {}\n", self.stack.remaining));
+        result.push_str(&format!("sequence {}\n", self.sequence));
+        if let Some(list) = &self.stack.list {
+            result.push_str("list\n");
+            for job in list {
+                result.push_str(&format!("queued {}\n", job.to_uri()));
+            }
+        }
+
+        // Pending jobs are handed out again after a restart
+        for job in self.pending.keys().chain(self.abandoned.iter()) {
+            result.push_str(&format!("available {}\n", job.to_uri()));
+        }
+        for job in &self.failed {
+            result.push_str(&format!("failed {}\n", job.to_uri()));
+        }
+        for run in self.completed.runs() {
+            result.push_str(&format!("completed {}..{}\n", run.start, run.end));
+        }
+        for (job, attempts) in &self.attempts {
+            result.push_str(&format!("attempts {} {}\n", job.to_uri(), attempts));
+        }
+        for (job, reason) in &self.reasons {
+            result.push_str(&format!("reason {} {}\n", job.to_uri(), json::escape(reason)));
+        }
+        return result;
+    }
+
+    pub fn from_checkpoint(input: &String) -> Result<Self, Error> {
+        let mut lines = input.lines();
+        if lines.next() != Some("netspatch-checkpoint 1") {
+            return Err(Error::UnexpectedString);
+        }
+        let dimensions = match lines.next().and_then(|line| line.strip_prefix("dimensions ")) {
+            Some(value) => Self::parse_numbers(value)?,
+            None => return Err(Error::UnexpectedString),
+        };
+        let mut manager = Self::new(&dimensions)?;
+
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "cursor" => {
+                    let parts: Vec<&str> = value.split(' ').collect();
+                    if parts.len() != dimensions.len() {
+                        return Err(Error::DimensionMismatch);
+                    }
+                    for (slice, part) in manager.stack.top.index.iter_mut().zip(parts) {
+                        let (index_str, length_str) = match part.split_once('+') {
+                            Some(value) => value,
+                            None => return Err(Error::UnexpectedString),
+                        };
+                        let numbers = Self::parse_numbers(&format!("{index_str} {length_str}"))?;
+                        slice.index = numbers[0];
+                        slice.length = numbers[1];
+                        if slice.length == 0 {
+                            return Err(Error::ZeroSizedDimension);
+                        }
+                    }
+                }
+                "remaining" => {
+                    manager.stack.remaining = Self::parse_numbers(value)?[0];
+                }
+                "sequence" => {
+                    manager.sequence = match value.parse::<u64>() {
+                        Ok(value) => value,
+                        Err(_) => return Err(Error::UnexpectedString),
+                    };
+                }
+                "list" => {
+                    manager.stack.list = Some(VecDeque::new());
+                }
+                "queued" => {
+                    let job = manager.from_uri(value.to_string())?;
+                    match manager.stack.list.as_mut() {
+                        Some(list) => list.push_back(job),
+                        None => return Err(Error::UnexpectedString),
+                    }
+                }
+                "available" => {
+                    let job = manager.from_uri(value.to_string())?;
+                    manager.abandoned.insert(job);
+                }
+                "completed" => {
+                    let (index, length) = JobDimension::parse_range(value)?;
+                    match index.checked_add(length) {
+                        Some(end) if end <= manager.completed.size => manager.completed.insert(index..end),
+                        _ => return Err(Error::OutOfBounds),
+                    };
+                }
+                "failed" => {
+                    let job = manager.from_uri(value.to_string())?;
+                    manager.failed.insert(job);
+                }
+                "attempts" => {
+                    let (uri, count_str) = match value.split_once(' ') {
+                        Some(value) => value,
+                        None => return Err(Error::UnexpectedString),
+                    };
+                    let job = manager.from_uri(uri.to_string())?;
+                    manager.attempts.insert(job, Self::parse_numbers(count_str)?[0]);
+                }
+                "reason" => {
+                    let (uri, reason_str) = match value.split_once(' ') {
+                        Some(value) => value,
+                        None => return Err(Error::UnexpectedString),
+                    };
+                    let job = manager.from_uri(uri.to_string())?;
+                    let reason = match json::unescape(reason_str) {
+                        Some(value) => value,
+                        None => return Err(Error::UnexpectedString),
+                    };
+                    manager.reasons.insert(job, reason);
+                }
+                &_ => return Err(Error::UnexpectedString),
+            }
+        }
+        return Ok(manager);
+    }
+
+    fn parse_numbers(input: &str) -> Result<Vec<usize>, Error> {
+        let mut result = Vec::new();
+        for part in input.split(' ') {
+            match part.parse::<usize>() {
+                Ok(value) => result.push(value),
+                Err(_) => return Err(Error::UnexpectedString),
+            }
+        }
+        return Ok(result);
+    }
+
+    fn from_stack(stack: JobStack) -> Self {
+        let size = stack.top.dimensions().iter().product();
+        return Self {
+            completed: RangeSet::new(size),
+            stack,
+            pending: HashMap::new(),
+            abandoned: HashSet::new(),
+            failed: HashSet::new(),
+            attempts: HashMap::new(),
+            reasons: HashMap::new(),
+            leases: HashMap::new(),
+            copies: HashMap::new(),
+            failed_copies: HashSet::new(),
+            dispatched: HashMap::new(),
+            // Seed tokens from the clock so they stay unique across restarts
+            next_lease: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or(Duration::ZERO).as_nanos() as u64,
+            lease_policy: LeasePolicy::Current,
+            parameters: Vec::new(),
+            lease: None,
+            max_attempts: None,
+            speculation: None,
+            dispatches: 0,
+            completions: 0,
+            abandons: 0,
+            expiries: 0,
+            checkpoint: None,
+            last_checkpoint: SystemTime::now(),
+            journal: None,
+            journal_error: None,
+            sequence: 0,
+        };
+    }
+
+    pub fn with_lease(&mut self, lease: Duration) -> &mut Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    pub fn with_lease_policy(&mut self, policy: LeasePolicy) -> &mut Self {
+        self.lease_policy = policy;
+        self
+    }
+
+    pub fn with_max_attempts(&mut self, max_attempts: usize) -> &mut Self {
+        assert!(max_attempts > 0);
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    pub fn with_speculation(&mut self, copies: usize) -> &mut Self {
+        assert!(copies > 0);
+        self.speculation = Some(copies);
+        self
+    }
+
+    fn set_pending(&mut self, job: &Job) -> u64 {
+        assert!(!self.pending.contains_key(job));
+        self.pending.insert(job.clone(), SystemTime::now());
+        self.dispatched.insert(job.clone(), SystemTime::now());
+        *self.attempts.entry(job.clone()).or_insert(0) += 1;
+        self.next_lease += 1;
+        self.leases.insert(job.clone(), self.next_lease);
+        return self.next_lease;
+    }
+
+    pub fn lease(&self, job: &Job) -> Option<u64> {
+        return self.leases.get(job).cloned();
+    }
+
+    fn holds_lease(&self, job: &Job, lease: u64) -> bool {
+        if self.lease(job) == Some(lease) {
+            return true;
+        }
+        return match self.copies.get(job) {
+            Some(copies) => copies.contains(&lease),
+            None => false,
+        };
+    }
+
+    pub fn copies(&self, job: &Job) -> usize {
+        return match self.copies.get(job) {
+            Some(copies) => copies.len(),
+            None => 0,
+        };
+    }
+
+    pub fn jobs_pending(&self) -> HashMap<Job, SystemTime> {
+        return self.pending.clone();
+    }
+
+    pub fn jobs_abandonded(&self) -> HashSet<Job> {
+        return self.abandoned.clone();
+    }
+
+    pub fn jobs_failed(&self) -> HashSet<Job> {
+        return self.failed.clone();
+    }
+
+    pub fn attempts(&self, job: &Job) -> usize {
+        return match self.attempts.get(job) {
+            Some(value) => *value,
+            None => 0,
+        };
+    }
+
+    pub fn dimensions(&self) -> Vec<usize> {
+        return self.stack.top.dimensions();
+    }
+
+    pub fn is_completed(&self, job: &Job) -> bool {
+        return job.linear_runs().all(|run| self.completed.contains_range(run));
+    }
+
+    pub fn jobs_completed(&self) -> usize {
+        return self.completed.len();
+    }
+
+    pub fn jobs_total(&self) -> usize {
+        // A job list only covers the points it names
+        if self.stack.list.is_some() {
+            let failed: usize = self.failed.iter().map(|job| job.size()).sum();
+            return self.jobs_completed() + self.jobs_outstanding() + failed;
+        }
+        return self.completed.size;
+    }
+
+    pub fn jobs_remaining(&self) -> usize {
+        return self.stack.remaining();
+    }
+
+    pub fn jobs_outstanding(&self) -> usize {
+        // Points still to be computed, failed jobs are not retried
+        let held: usize = self.pending.keys().chain(self.abandoned.iter()).map(|job| job.size()).sum();
+        return self.stack.remaining() + held;
+    }
+
+    pub fn pending_count(&self) -> usize {
+        return self.pending.len();
+    }
+
+    pub fn abandoned_count(&self) -> usize {
+        return self.abandoned.len();
+    }
+
+    pub fn failed_count(&self) -> usize {
+        return self.failed.len();
+    }
+
+    pub fn dispatch_count(&self) -> usize {
+        return self.dispatches;
+    }
+
+    pub fn completion_count(&self) -> usize {
+        return self.completions;
+    }
+
+    pub fn abandon_count(&self) -> usize {
+        return self.abandons;
+    }
+
+    pub fn expiry_count(&self) -> usize {
+        return self.expiries;
+    }
+
+    pub fn is_outstanding(&self, job: &Job) -> bool {
+        return self.pending.contains_key(job) || self.abandoned.contains(job) || self.failed.contains(job);
+    }
+
+    pub fn reason(&self, job: &Job) -> Option<String> {
+        return self.reasons.get(job).cloned();
+    }
+
+    pub fn failures(&self) -> Vec<(Job, String)> {
+        let mut result: Vec<(Job, String)> = self.reasons.iter().map(|(job, reason)| (job.clone(), reason.clone())).collect();
+        result.sort_by_key(|(job, _)| job.to_uri());
+        return result;
+    }
+
+    pub fn point_states(&self) -> Vec<PointState> {
+        // Indexed like the completed set
+        let mut result = vec![PointState::Available; self.completed.size];
+        if let Some(list) = &self.stack.list {
+            result = vec![PointState::Unlisted; self.completed.size];
+            for job in list.iter().chain(self.abandoned.iter()) {
+                for point in job.indices() {
+                    result[job.linear_index(&point)] = PointState::Available;
+                }
+            }
+        }
+        for (i, state) in result.iter_mut().enumerate() {
+            if self.completed.contains(i) {
+                *state = PointState::Done;
+            }
+        }
+        for (jobs, state) in [(self.pending.keys().collect::<Vec<&Job>>(), PointState::Pending), (self.failed.iter().collect(), PointState::Failed)] {
+            for job in jobs {
+                for point in job.indices() {
+                    result[job.linear_index(&point)] = state;
+                }
+            }
+        }
+        return result;
+    }
+
+    pub fn from_uri(&self, uri: String) -> Result<Job, Error> {
+        return Job::from_uri(&uri, &self.stack.top.dimensions());
+    }
+
+    pub fn expire(&mut self) -> Vec<Job> {
+        let lease = match self.lease {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+
+        // Collect every pending job whose lease has run out
+        let now = SystemTime::now();
+        let mut expired = Vec::new();
+        for (job, since) in &self.pending {
+            let elapsed = now.duration_since(*since).unwrap_or(Duration::ZERO);
+            if elapsed >= lease {
+                expired.push(job.clone());
+            }
+        }
+
+        // Jobs whose abandon could not be journaled stay pending and are tried again next time
+        expired.retain(|job| self.abandon(job).is_ok());
+        self.expiries += expired.len();
+        return expired;
+    }
+
+    pub fn pop(&mut self) -> Option<Job> {
+        self.expire();
+        return self.next_job(&Vec::new());
+    }
+
+    pub fn pop_many(&mut self, count: usize) -> Vec<Job> {
+        self.expire();
+        let mut result = Vec::new();
+        while result.len() < count {
+            match self.next_job(&result) {
+                Some(job) => result.push(job),
+                None => break,
+            }
+        }
+        return result;
+    }
+
+    // Jobs in taken are already part of the same request and are never copied into it
+    fn next_job(&mut self, taken: &Vec<Job>) -> Option<Job> {
+        let result = self.next_dispatch(taken);
+        if result.is_some() {
+            self.dispatches += 1;
+        }
+        return result;
+    }
+
+    fn next_dispatch(&mut self, taken: &Vec<Job>) -> Option<Job> {
+        if !self.abandoned.is_empty() {
+            let mut result = self.abandoned.iter().next().cloned().unwrap();
+            // Nothing is handed out unless it is journaled, journal_error reports why
+            if self.record(format!("pop {}", result.to_uri())).is_err() {
+                return None;
+            }
+            self.abandoned.remove(&result);
+            result.parameters = self.parameters.clone();
+            result.lease = Some(self.set_pending(&result));
+            return Some(result);
+        } else if !self.stack.is_empty() {
+            let mut result = self.stack.next()?;
+            if self.record(format!("pop {}", result.to_uri())).is_err() {
+                // The stack cannot step back, so the job waits with the abandoned ones
+                self.abandoned.insert(result);
+                return None;
+            }
+            result.parameters = self.parameters.clone();
+            assert!(!self.pending.contains_key(&result));
+            result.lease = Some(self.set_pending(&result));
+            assert!(self.pending.contains_key(&result));
+            return Some(result);
+        } else {
+            return self.speculate(taken);
+        }
+    }
+
+    fn speculate(&mut self, taken: &Vec<Job>) -> Option<Job> {
+        let copies = self.speculation?;
+
+        // Duplicate the job that has been running the longest, unless a copy of it already failed.
+        // Heartbeats move the pending time, so the ranking uses the time of dispatch instead.
+        let mut oldest: Option<(&Job, &SystemTime)> = None;
+        for job in self.pending.keys() {
+            if self.copies(job) >= copies || self.failed_copies.contains(job) || taken.contains(job) {
+                continue;
+            }
+            let since = match self.dispatched.get(job) {
+                Some(value) => value,
+                None => &self.pending[job],
+            };
+            if oldest.is_none() || since < oldest.unwrap().1 {
+                oldest = Some((job, since));
+            }
+        }
+        let mut result = oldest?.0.clone();
+
+        // Copies are not attempts and are dropped on a restart, so they are not journaled
+        self.next_lease += 1;
+        self.copies.entry(result.clone()).or_default().push(self.next_lease);
+        result.parameters = self.parameters.clone();
+        result.lease = Some(self.next_lease);
+        return Some(result);
+    }
+
+    pub fn check_completion(&self, job: &Job, lease: Option<u64>) -> Result<(), Error> {
+        if !self.is_outstanding(job) {
+            if !self.is_completed(job) {
+                return Err(Error::JobNotFound);
+            } else if self.lease_policy == LeasePolicy::LastWins {
+                return Ok(());
+            }
+            return Err(Error::AlreadyCompleted);
+        }
+
+        // Completions without a token are not fenced
+        if let Some(lease) = lease {
+            if self.leases.contains_key(job) && !self.holds_lease(job, lease) && self.lease_policy == LeasePolicy::Current {
+                return Err(Error::StaleLease);
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn complete(&mut self, uri: String) -> Result<Job, Error> {
+        return self.complete_with_lease(uri, None);
+    }
+
+    pub fn complete_with_lease(&mut self, uri: String, lease: Option<u64>) -> Result<Job, Error> {
+        let job = self.from_uri(uri)?;
+        self.check_completion(&job, lease)?;
+        if !self.is_outstanding(&job) {
+            // A repeated completion under the last-wins policy changes nothing
+            return Ok(job);
+        }
+
+        // Journal first so a lost entry leaves the job as it was
+        if let Err(err) = self.record(format!("complete {}", job.to_uri())) {
+            return Err(Error::Journal(err));
+        }
+
+        // A late result still resolves a job that ran out of attempts
         self.pending.remove(&job);
-        self.abandoned.insert(job.clone());
+        self.dispatched.remove(&job);
+        self.abandoned.remove(&job);
+        self.failed.remove(&job);
+        self.leases.remove(&job);
+        self.copies.remove(&job);
+        self.failed_copies.remove(&job);
+        for run in job.linear_runs() {
+            self.completed.insert(run);
+        }
+        self.attempts.remove(&job);
+        self.reasons.remove(&job);
+        self.completions += 1;
+        return Ok(job);
+    }
+
+    pub fn fail(&mut self, uri: String, reason: String) -> Result<Job, Error> {
+        return self.fail_with_lease(uri, reason, None);
+    }
+
+    pub fn fail_with_lease(&mut self, uri: String, reason: String, lease: Option<u64>) -> Result<Job, Error> {
+        let job = self.from_uri(uri)?;
+        if !self.pending.contains_key(&job) {
+            return Err(Error::JobNotFound);
+        }
+        // A stale worker must never requeue a job that was handed to someone else
+        if let Some(lease) = lease {
+            if !self.holds_lease(&job, lease) {
+                return Err(Error::StaleLease);
+            }
+            if self.lease(&job) != Some(lease) {
+                // A failed copy leaves the original dispatch running but is not retried
+                self.copies.get_mut(&job).unwrap().retain(|copy| *copy != lease);
+                self.failed_copies.insert(job.clone());
+                return Ok(job);
+            }
+        }
+        if let Err(err) = self.record(format!("fail {} {}", job.to_uri(), json::escape(&reason))) {
+            return Err(Error::Journal(err));
+        }
+        self.reasons.insert(job.clone(), reason);
+        self.requeue(&job);
+        return Ok(job);
+    }
+
+    pub fn renew(&mut self, uri: String, lease: Option<u64>) -> Result<Job, Error> {
+        let job = self.from_uri(uri)?;
+        if !self.pending.contains_key(&job) {
+            return Err(Error::JobNotFound);
+        }
+        if let Some(lease) = lease {
+            if !self.holds_lease(&job, lease) {
+                return Err(Error::StaleLease);
+            }
+        }
+        self.pending.insert(job.clone(), SystemTime::now());
+        return Ok(job);
+    }
+
+    pub fn abandon(&mut self, job: &Job) -> Result<(), io::Error> {
+        assert!(self.pending.contains_key(job));
+        self.record(format!("abandon {}", job.to_uri()))?;
+        self.requeue(job);
+        return Ok(());
+    }
+
+    fn requeue(&mut self, job: &Job) {
+        assert!(self.pending.contains_key(&job));
+        assert!(!self.abandoned.contains(&job));
+        assert!(!self.failed.contains(job));
+        self.pending.remove(&job);
+        self.dispatched.remove(job);
+        self.leases.remove(job);
+        self.copies.remove(job);
+        self.failed_copies.remove(job);
+        self.abandons += 1;
+        let exhausted = match self.max_attempts {
+            Some(max_attempts) => self.attempts(job) >= max_attempts,
+            None => false,
+        };
+        if exhausted {
+            self.failed.insert(job.clone());
+        } else {
+            self.abandoned.insert(job.clone());
+        }
     }
 
     pub fn is_finished(&self) -> bool {
@@ -433,7 +1689,7 @@ mod tests {
             let job = manager.pop().unwrap();
             assert_eq!(manager.jobs_pending().len(), 1);
             assert_eq!(manager.jobs_abandonded().len(), 0);
-            manager.abandon(&job);
+            manager.abandon(&job).unwrap();
             assert_eq!(manager.jobs_pending().len(), 0);
             assert_eq!(manager.jobs_abandonded().len(), 1);
             let echo = manager.complete(job.to_uri()).unwrap();
@@ -442,4 +1698,609 @@ mod tests {
             assert_eq!(manager.jobs_abandonded().len(), 0);
         }
     }
+
+    #[test]
+    fn test_lease_expiry() {
+        let dimensions = vec![2, 2];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        assert!(manager.expire().is_empty());
+        let job = manager.pop().unwrap();
+        assert!(manager.expire().is_empty());
+
+        manager.with_lease(Duration::ZERO);
+        let expired = manager.expire();
+        assert_eq!(expired.len(), 1);
+        assert!(expired.first().unwrap().eq(&job));
+        assert_eq!(manager.jobs_pending().len(), 0);
+        assert_eq!(manager.jobs_abandonded().len(), 1);
+
+        // The expired job is handed out again before the stack advances
+        let again = manager.pop().unwrap();
+        assert!(again.eq(&job));
+        assert_eq!(manager.jobs_pending().len(), 1);
+        assert_eq!(manager.jobs_abandonded().len(), 0);
+        assert_eq!(manager.dispatch_count(), 2);
+        assert_eq!(manager.abandon_count(), 1);
+        assert_eq!(manager.expiry_count(), 1);
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let dimensions = vec![2];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_max_attempts(2);
+
+        let job = manager.pop().unwrap();
+        assert_eq!(manager.attempts(&job), 1);
+        manager.abandon(&job).unwrap();
+        assert_eq!(manager.jobs_abandonded().len(), 1);
+
+        let again = manager.pop().unwrap();
+        assert!(again.eq(&job));
+        assert_eq!(manager.attempts(&job), 2);
+        manager.abandon(&job).unwrap();
+        assert_eq!(manager.jobs_abandonded().len(), 0);
+        assert_eq!(manager.jobs_failed().len(), 1);
+        assert!(manager.jobs_failed().contains(&job));
+
+        // The failed job counts as resolved
+        let other = manager.pop().unwrap();
+        assert!(!other.eq(&job));
+        assert!(!manager.is_finished());
+        manager.complete(other.to_uri()).unwrap();
+        assert!(manager.pop().is_none());
+        assert!(manager.is_finished());
+    }
+
+    #[test]
+    fn test_fail() {
+        let dimensions = vec![2];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_max_attempts(2);
+        assert!(manager.fail("0".to_string(), "not dispatched".to_string()).is_err());
+
+        let job = manager.pop().unwrap();
+        let echo = manager.fail(job.to_uri(), "first".to_string()).unwrap();
+        assert!(echo.eq(&job));
+        assert_eq!(manager.jobs_pending().len(), 0);
+        assert!(manager.jobs_abandonded().contains(&job));
+        assert_eq!(manager.reason(&job).unwrap(), "first");
+
+        let again = manager.pop().unwrap();
+        assert!(again.eq(&job));
+        manager.fail(job.to_uri(), "second".to_string()).unwrap();
+        assert!(manager.jobs_failed().contains(&job));
+        assert_eq!(manager.reason(&job).unwrap(), "second");
+    }
+
+    #[test]
+    fn test_pop_many() {
+        let dimensions = vec![2, 3];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        let first = manager.pop_many(4);
+        assert_eq!(first.len(), 4);
+        assert_eq!(manager.jobs_pending().len(), 4);
+        manager.abandon(first.first().unwrap()).unwrap();
+
+        let second = manager.pop_many(4);
+        assert_eq!(second.len(), 3);
+        assert!(second.contains(first.first().unwrap()));
+        assert_eq!(manager.jobs_pending().len(), 6);
+        assert!(manager.pop_many(4).is_empty());
+
+        // Round trip the job list through the wire format
+        let parsed = Job::parse_list(&Job::list_to_string(&second)).unwrap();
+        assert!(parsed.eq(&second));
+    }
+
+    #[test]
+    fn test_job_result_list() {
+        let dimensions = vec![2, 3];
+        let results = vec![
+            JobResult::new(&Job::new(&vec![0, 1], &dimensions).unwrap(), b"first\r\nline".to_vec()),
+            JobResult::new(&Job::new(&vec![1, 0], &dimensions).unwrap(), vec![0, 0xff, b'\r']),
+            JobResult::new(&Job::new(&vec![1, 2], &dimensions).unwrap(), Vec::new()),
+        ];
+        let parsed = JobResult::parse_list(&JobResult::list_to_bytes(&results)).unwrap();
+        assert_eq!(parsed, results);
+        assert!(JobResult::parse_list(b"0/1 10\r\nshort\r\n").is_err());
+    }
+
+    #[test]
+    fn test_chunked_dimension() {
+        let dimension = JobDimension::parse(&"4..8/10".to_string()).unwrap();
+        assert_eq!(dimension.index, 4);
+        assert_eq!(dimension.length, 4);
+        assert_eq!(dimension.range(), 4..8);
+        assert_eq!(dimension.bounds(), (0.4, 0.8));
+        assert_eq!(dimension.to_string(), "4..8/10");
+        assert!(JobDimension::parse(&"8..12/10".to_string()).is_err());
+        assert!(JobDimension::parse(&"8..8/10".to_string()).is_err());
+
+        let mut mirror = 0;
+        for index in JobDimension::chunked(10, 3).unwrap() {
+            assert_eq!(mirror, index);
+            mirror += 3;
+        }
+        assert_eq!(mirror, 12);
+    }
+
+    #[test]
+    fn test_chunked_stack() {
+        let mut stack = JobStack::chunked(&vec![5, 2], &vec![2, 1]).unwrap();
+        let mut uris = Vec::new();
+        let mut total = 0;
+        for job in &mut stack {
+            total += job.size();
+            uris.push(job.to_uri());
+        }
+        assert_eq!(uris, vec!["0..2/0", "0..2/1", "2..4/0", "2..4/1", "4/0", "4/1"]);
+        assert_eq!(total, 10);
+        assert!(JobStack::chunked(&vec![5, 2], &vec![0, 1]).is_err());
+        assert!(JobStack::chunked(&vec![5, 2], &vec![1]).is_err());
+    }
+
+    #[test]
+    fn test_chunked_complete() {
+        let dimensions = vec![5, 3];
+        let mut manager = JobManager::chunked(&dimensions, &vec![2, 3]).unwrap();
+        let job = manager.pop().unwrap();
+        assert_eq!(job.to_uri(), "0..2/0..3");
+        let points: Vec<Vec<usize>> = job.indices().collect();
+        assert_eq!(points.len(), 6);
+        assert_eq!(points.first().unwrap(), &vec![0, 0]);
+        assert_eq!(points.last().unwrap(), &vec![1, 2]);
+
+        let parsed = Job::parse(&job.to_string()).unwrap();
+        assert!(parsed.eq(&job));
+        assert!(manager.complete("0/0".to_string()).is_err());
+        assert_eq!(manager.jobs_outstanding(), 15);
+        let echo = manager.complete(job.to_uri()).unwrap();
+        assert!(echo.eq(&job));
+        assert_eq!(manager.jobs_total(), 15);
+        assert_eq!(manager.jobs_completed(), 6);
+        assert_eq!(manager.jobs_remaining(), 9);
+        assert_eq!(manager.jobs_outstanding(), 9);
+        assert_eq!(manager.pending_count(), 0);
+
+        let next = manager.pop().unwrap();
+        assert_eq!(next.to_uri(), "2..4/0..3");
+        let states = manager.point_states();
+        assert_eq!(states[0], PointState::Done);
+        assert_eq!(states[6], PointState::Pending);
+        assert_eq!(states[12], PointState::Available);
+    }
+
+    #[test]
+    fn test_guided_schedule() {
+        let mut stack = JobStack::new(&vec![100]).unwrap();
+        stack.with_schedule(Schedule::Guided { min_chunk: 1, workers: 4 });
+        let mut sizes = Vec::new();
+        for job in &mut stack {
+            sizes.push(job.size());
+        }
+        assert_eq!(sizes.iter().sum::<usize>(), 100);
+        assert_eq!(*sizes.first().unwrap(), 25);
+        assert_eq!(*sizes.last().unwrap(), 1);
+        for pair in sizes.windows(2) {
+            assert!(pair[0] >= pair[1]);
+        }
+        assert_eq!(stack.remaining(), 0);
+
+        // Chunks never cross into the next row and respect the minimum
+        let mut stack = JobStack::new(&vec![2, 10]).unwrap();
+        stack.with_schedule(Schedule::Guided { min_chunk: 3, workers: 2 });
+        let uris: Vec<String> = stack.map(|job| job.to_uri()).collect();
+        assert_eq!(uris, vec!["0/0..10", "1/0..5", "1/5..8", "1/8..10"]);
+
+        // Blocks span the outer dimensions too and ignore the static chunks
+        let mut stack = JobStack::chunked(&vec![40, 4], &vec![10, 1]).unwrap();
+        stack.with_schedule(Schedule::Guided { min_chunk: 1, workers: 4 });
+        let jobs: Vec<Job> = stack.collect();
+        assert_eq!(jobs.first().unwrap().to_uri(), "0..10/0..4");
+        let sizes: Vec<usize> = jobs.iter().map(|job| job.size()).collect();
+        assert_eq!(sizes, vec![40, 28, 20, 16, 12, 8, 8, 4, 4, 4, 4, 3, 1, 2, 2, 1, 1, 1, 1]);
+
+        let mut stack = JobStack::new(&vec![1000, 4]).unwrap();
+        stack.with_schedule(Schedule::Guided { min_chunk: 8, workers: 4 });
+        let sizes: Vec<usize> = stack.map(|job| job.size()).collect();
+        assert_eq!(sizes.iter().sum::<usize>(), 4000);
+        assert_eq!(*sizes.first().unwrap(), 1000);
+        assert!(sizes.iter().all(|size| size % 4 == 0));
+        for pair in sizes.windows(2) {
+            assert!(pair[0] >= pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_from_jobs() {
+        let dimensions = vec![4, 4];
+        let jobs = vec![
+            Job::from_uri(&"3/1".to_string(), &dimensions).unwrap(),
+            Job::from_uri(&"0/2".to_string(), &dimensions).unwrap(),
+            Job::from_uri(&"3/1".to_string(), &dimensions).unwrap(),
+        ];
+        let mut manager = JobManager::from_jobs(&dimensions, jobs).unwrap();
+        let first = manager.pop().unwrap();
+        assert_eq!(first.to_uri(), "3/1");
+        manager.abandon(&first).unwrap();
+        let second = manager.pop().unwrap();
+        assert!(second.eq(&first));
+        let third = manager.pop().unwrap();
+        assert_eq!(third.to_uri(), "0/2");
+        assert!(manager.pop().is_none());
+        assert_eq!(manager.jobs_total(), 2);
+        let states = manager.point_states();
+        assert_eq!(states.iter().filter(|state| **state == PointState::Unlisted).count(), 14);
+        assert_eq!(states[13], PointState::Pending);
+        manager.complete(first.to_uri()).unwrap();
+        manager.complete(third.to_uri()).unwrap();
+        assert!(manager.is_finished());
+
+        // Progress is measured against the listed points only
+        assert_eq!(manager.jobs_total(), 2);
+        assert_eq!(manager.jobs_completed(), 2);
+        let restored = JobManager::from_checkpoint(&manager.to_checkpoint()).unwrap();
+        assert_eq!(restored.jobs_total(), 2);
+
+        let other = Job::new(&vec![0], &vec![4]).unwrap();
+        assert!(JobManager::from_jobs(&dimensions, vec![other]).is_err());
+        assert!(JobManager::from_jobs(&dimensions, Vec::new()).unwrap().is_finished());
+    }
+
+    #[test]
+    fn test_named_parameters() {
+        let dimensions = vec![3, 4];
+        let mut manager = JobManager::chunked(&dimensions, &vec![1, 2]).unwrap();
+        let too_short = vec![Parameter::parse("model=list:a,b").unwrap(), Parameter::parse("lr").unwrap()];
+        assert!(manager.with_parameters(too_short).is_err());
+        assert!(manager.with_parameters(vec![Parameter::parse("lr").unwrap()]).is_err());
+        let overflow = vec![Parameter::parse("model").unwrap(), Parameter::parse(&format!("seed=range:0:{}", i64::MAX / 2)).unwrap()];
+        assert!(matches!(manager.with_parameters(overflow), Err(Error::OutOfBounds)));
+        let parameters = vec![
+            Parameter::parse("model=list:resnet,vgg,vit").unwrap(),
+            Parameter::parse("lr=linspace:0:3").unwrap(),
+        ];
+        manager.with_parameters(parameters).unwrap();
+
+        manager.pop().unwrap();
+        let job = manager.pop().unwrap();
+        let expected = format!("Lease: {}\r\n0/3 model=list:resnet,vgg,vit\r\n2..4/4 lr=linspace:0:3\r\n", job.lease.unwrap());
+        assert_eq!(job.to_string(), expected);
+
+        // The client sees the same values after parsing the wire format
+        let parsed = Job::parse(&job.to_string()).unwrap();
+        assert!(parsed.eq(&job));
+        assert_eq!(parsed.param("model").unwrap().as_str(), Some("resnet"));
+        assert_eq!(parsed.param("lr").unwrap().as_f64(), Some(2.0));
+        assert_eq!(parsed.param_at("lr", 3).unwrap().as_f64(), Some(3.0));
+        assert!(parsed.param_at("lr", 1).is_none());
+        assert!(parsed.param("missing").is_none());
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dimensions = vec![3, 4];
+        let mut manager = JobManager::chunked(&dimensions, &vec![1, 2]).unwrap();
+        manager.with_max_attempts(2);
+        let completed = manager.pop().unwrap();
+        let pending = manager.pop().unwrap();
+        let failing = manager.pop().unwrap();
+        manager.complete(completed.to_uri()).unwrap();
+        manager.fail(failing.to_uri(), "bad \"input\"\r\nline".to_string()).unwrap();
+
+        let mut restored = JobManager::from_checkpoint(&manager.to_checkpoint()).unwrap();
+        assert_eq!(restored.jobs_pending().len(), 0);
+        assert_eq!(restored.jobs_abandonded().len(), 2);
+        assert!(restored.jobs_abandonded().contains(&pending));
+        assert_eq!(restored.attempts(&failing), 1);
+        assert_eq!(restored.reason(&failing).unwrap(), "bad \"input\"\r\nline");
+
+        // Every job except the completed one is dispatched again
+        let mut uris = HashSet::new();
+        while let Some(job) = restored.pop() {
+            assert!(!job.eq(&completed));
+            uris.insert(job.to_uri());
+        }
+        assert_eq!(uris.len(), 5);
+        assert!(uris.contains(&pending.to_uri()));
+        assert!(uris.contains("2/2..4"));
+
+        assert!(JobManager::from_checkpoint(&"not a checkpoint".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_list() {
+        let dimensions = vec![4, 4];
+        let jobs = vec![
+            Job::from_uri(&"3/1".to_string(), &dimensions).unwrap(),
+            Job::from_uri(&"0/2".to_string(), &dimensions).unwrap(),
+        ];
+        let mut manager = JobManager::from_jobs(&dimensions, jobs).unwrap();
+        manager.pop().unwrap();
+        let mut restored = JobManager::from_checkpoint(&manager.to_checkpoint()).unwrap();
+        assert_eq!(restored.pop().unwrap().to_uri(), "3/1");
+        assert_eq!(restored.pop().unwrap().to_uri(), "0/2");
+        assert!(restored.pop().is_none());
+    }
+
+    #[test]
+    fn test_journal_replay() {
+        let base = std::env::temp_dir().join(format!("netspatch-replay-{}", std::process::id()));
+        let journal = base.with_extension("journal");
+        let state = base.with_extension("state");
+        let dimensions = vec![2, 3];
+        let (completed, failing) = {
+            let mut manager = JobManager::new(&dimensions).unwrap();
+            manager.with_max_attempts(1).with_checkpoint(&state, Duration::ZERO);
+            manager.with_journal(&journal).unwrap();
+            let completed = manager.pop().unwrap();
+            manager.complete(completed.to_uri()).unwrap();
+            manager.checkpoint().unwrap();
+            let snapshot = fs::read_to_string(&journal).unwrap();
+            assert!(snapshot.is_empty());
+
+            let failing = manager.pop().unwrap();
+            manager.fail(failing.to_uri(), "broken".to_string()).unwrap();
+            let pending = manager.pop_many(2);
+            manager.complete(pending[1].to_uri()).unwrap();
+            (vec![completed, pending[1].clone()], failing)
+        };
+
+        // Rebuild from the checkpoint and the journal written after it
+        let contents = fs::read_to_string(&state).unwrap();
+        let mut manager = JobManager::from_checkpoint(&contents).unwrap();
+        manager.with_max_attempts(1).with_checkpoint(&state, Duration::MAX);
+        manager.with_journal(&journal).unwrap();
+        assert!(manager.jobs_failed().contains(&failing));
+        assert_eq!(manager.reason(&failing).unwrap(), "broken");
+        assert_eq!(manager.jobs_pending().len(), 0);
+        assert_eq!(manager.jobs_abandonded().len(), 1);
+        let mut remaining = 0;
+        while let Some(job) = manager.pop() {
+            assert!(!completed.contains(&job));
+            remaining += 1;
+        }
+        assert_eq!(remaining, 3);
+
+        // Replaying the same journal again on top of a newer checkpoint is harmless
+        manager.with_checkpoint(&state, Duration::ZERO);
+        let entries = fs::read_to_string(&journal).unwrap();
+        manager.checkpoint().unwrap();
+        fs::write(&journal, entries).unwrap();
+        let contents = fs::read_to_string(&state).unwrap();
+        let mut again = JobManager::from_checkpoint(&contents).unwrap();
+        again.with_checkpoint(&state, Duration::MAX);
+        again.with_journal(&journal).unwrap();
+        assert_eq!(again.jobs_abandonded().len(), 3);
+
+        fs::remove_file(&journal).unwrap();
+        fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn test_journal_after_checkpoint() {
+        let base = std::env::temp_dir().join(format!("netspatch-reclaim-{}", std::process::id()));
+        let journal = base.with_extension("journal");
+        let state = base.with_extension("state");
+        let dimensions = vec![3];
+        let (abandoned, failing) = {
+            let mut manager = JobManager::new(&dimensions).unwrap();
+            manager.with_max_attempts(1).with_checkpoint(&state, Duration::ZERO);
+            manager.with_journal(&journal).unwrap();
+            let abandoned = manager.pop().unwrap();
+            let failing = manager.pop().unwrap();
+            manager.checkpoint().unwrap();
+
+            // Both jobs were running when the checkpoint was taken
+            manager.with_max_attempts(2);
+            manager.abandon(&abandoned).unwrap();
+            manager.fail(failing.to_uri(), "broken".to_string()).unwrap();
+            (abandoned, failing)
+        };
+
+        let contents = fs::read_to_string(&state).unwrap();
+        let mut manager = JobManager::from_checkpoint(&contents).unwrap();
+        manager.with_max_attempts(1).with_checkpoint(&state, Duration::MAX);
+        manager.with_journal(&journal).unwrap();
+        assert!(manager.jobs_failed().contains(&abandoned));
+        assert!(manager.jobs_failed().contains(&failing));
+        assert_eq!(manager.reason(&failing).unwrap(), "broken");
+        assert_eq!(manager.jobs_pending().len(), 0);
+        assert_eq!(manager.jobs_abandonded().len(), 0);
+
+        fs::remove_file(&journal).unwrap();
+        fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn test_journal_error() {
+        let base = std::env::temp_dir().join(format!("netspatch-journal-error-{}", std::process::id()));
+        let journal = base.with_extension("journal");
+        let state = base.with_extension("state");
+        let mut manager = JobManager::new(&vec![3]).unwrap();
+        manager.with_checkpoint(&state, Duration::ZERO);
+        manager.with_journal(&journal).unwrap();
+        let job = manager.pop().unwrap();
+
+        // Without a checkpoint the journal would never be truncated
+        let mut unchecked = JobManager::new(&vec![3]).unwrap();
+        assert!(unchecked.with_journal(&journal).is_err());
+
+        // A lost entry is reported rather than panicking and nothing more is appended
+        manager.journal_error = Some(io::Error::other("disk full"));
+        assert!(matches!(manager.complete(job.to_uri()), Err(Error::Journal(_))));
+        assert!(manager.pending.contains_key(&job));
+        assert!(manager.pop().is_none());
+        assert!(manager.journal_error().is_some());
+        assert_eq!(fs::read_to_string(&journal).unwrap().lines().count(), 1);
+
+        // A checkpoint clears the error and the held back job is dispatched again
+        manager.checkpoint().unwrap();
+        assert!(manager.journal_error().is_none());
+        assert!(manager.complete(job.to_uri()).is_ok());
+        assert_eq!(manager.pop().unwrap().to_uri(), "1");
+        manager.checkpoint().unwrap();
+        let restored = JobManager::from_checkpoint(&fs::read_to_string(&state).unwrap()).unwrap();
+        assert!(restored.is_completed(&job));
+
+        fs::remove_file(&journal).unwrap();
+        fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn test_completed() {
+        let dimensions = vec![3, 4];
+        let mut manager = JobManager::chunked(&dimensions, &vec![1, 2]).unwrap();
+        let first = manager.pop().unwrap();
+        let second = manager.pop().unwrap();
+        assert!(!manager.is_completed(&first));
+        manager.complete(first.to_uri()).unwrap();
+        assert!(manager.is_completed(&first));
+        assert_eq!(manager.jobs_completed(), 2);
+
+        // A repeated completion is told apart from an unknown job
+        assert!(matches!(manager.complete(first.to_uri()), Err(Error::AlreadyCompleted)));
+        assert!(matches!(manager.complete("2/0..2".to_string()), Err(Error::JobNotFound)));
+        assert!(matches!(manager.complete("9/0".to_string()), Err(Error::OutOfBounds)));
+
+        manager.complete(second.to_uri()).unwrap();
+        let restored = JobManager::from_checkpoint(&manager.to_checkpoint()).unwrap();
+        assert!(restored.to_checkpoint().contains("completed 0..4\n"));
+        assert!(restored.is_completed(&second));
+        assert_eq!(restored.jobs_completed(), 4);
+
+        // A block that stops short of the row end is split into one run per row
+        let mut manager = JobManager::chunked(&dimensions, &vec![2, 2]).unwrap();
+        let first = manager.pop().unwrap();
+        manager.complete(first.to_uri()).unwrap();
+        assert!(manager.to_checkpoint().contains("completed 0..2\ncompleted 4..6\n"));
+        assert!(manager.is_completed(&Job::from_uri(&"1/1".to_string(), &dimensions).unwrap()));
+        assert!(!manager.is_completed(&Job::from_uri(&"0/1..3".to_string(), &dimensions).unwrap()));
+
+        // Large spaces cost memory per completed run rather than per point
+        assert!(matches!(JobManager::new(&vec![1 << 40, 1 << 40]), Err(Error::OutOfBounds)));
+        let mut manager = JobManager::chunked(&vec![1 << 20, 1 << 20], &vec![1 << 10, 1 << 20]).unwrap();
+        let first = manager.pop().unwrap();
+        let second = manager.pop().unwrap();
+        manager.complete(second.to_uri()).unwrap();
+        manager.complete(first.to_uri()).unwrap();
+        assert!(manager.is_completed(&first));
+        assert_eq!(manager.jobs_completed(), 1 << 31);
+        assert!(manager.to_checkpoint().contains(&format!("completed 0..{}\n", 1_usize << 31)));
+    }
+
+    #[test]
+    fn test_lease_tokens() {
+        let dimensions = vec![2];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        let first = manager.pop().unwrap();
+        let stale = first.lease.unwrap();
+        assert_eq!(manager.lease(&first), Some(stale));
+        let parsed = Job::parse(&first.to_string()).unwrap();
+        assert_eq!(parsed.lease, Some(stale));
+
+        // The job is reissued and the first worker reports late
+        manager.abandon(&first).unwrap();
+        assert_eq!(manager.lease(&first), None);
+        let second = manager.pop().unwrap();
+        assert!(second.eq(&first));
+        let current = second.lease.unwrap();
+        assert!(current != stale);
+        assert!(matches!(manager.complete_with_lease(first.to_uri(), Some(stale)), Err(Error::StaleLease)));
+        assert!(matches!(manager.fail_with_lease(first.to_uri(), "late".to_string(), Some(stale)), Err(Error::StaleLease)));
+        manager.complete_with_lease(second.to_uri(), Some(current)).unwrap();
+        assert!(matches!(manager.complete_with_lease(first.to_uri(), Some(stale)), Err(Error::AlreadyCompleted)));
+
+        // First-wins accepts any token for an outstanding job
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_lease_policy(LeasePolicy::FirstWins);
+        let first = manager.pop().unwrap();
+        manager.abandon(&first).unwrap();
+        manager.pop().unwrap();
+        manager.complete_with_lease(first.to_uri(), first.lease).unwrap();
+        assert!(manager.complete(first.to_uri()).is_err());
+
+        // Last-wins also accepts repeated completions
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_lease_policy(LeasePolicy::LastWins);
+        let first = manager.pop().unwrap();
+        manager.complete(first.to_uri()).unwrap();
+        manager.complete_with_lease(first.to_uri(), Some(stale)).unwrap();
+        assert_eq!(manager.jobs_completed(), 1);
+    }
+
+    #[test]
+    fn test_renew() {
+        let dimensions = vec![2];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_lease(Duration::from_millis(50));
+        let job = manager.pop().unwrap();
+        assert!(matches!(manager.renew(job.to_uri(), Some(job.lease.unwrap() + 1)), Err(Error::StaleLease)));
+        assert!(matches!(manager.renew("1".to_string(), None), Err(Error::JobNotFound)));
+
+        // Renewing keeps the lease alive past its original expiry
+        for _ in 0..4 {
+            std::thread::sleep(Duration::from_millis(20));
+            manager.renew(job.to_uri(), job.lease).unwrap();
+            assert!(manager.expire().is_empty());
+        }
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(manager.expire().len(), 1);
+    }
+
+    #[test]
+    fn test_speculation() {
+        let dimensions = vec![3];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_speculation(1);
+        let first = manager.pop().unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        let second = manager.pop().unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        let third = manager.pop().unwrap();
+
+        // Copies go to the oldest pending jobs first, once each
+        let copy = manager.pop().unwrap();
+        assert!(copy.eq(&first));
+        assert_ne!(copy.lease, first.lease);
+        assert_eq!(manager.attempts(&first), 1);
+        assert!(manager.pop().unwrap().eq(&second));
+        assert!(manager.pop().unwrap().eq(&third));
+        assert!(manager.pop().is_none());
+
+        // The first completion wins and the duplicate is dropped
+        manager.complete_with_lease(copy.to_uri(), copy.lease).unwrap();
+        assert!(matches!(manager.complete_with_lease(first.to_uri(), first.lease), Err(Error::AlreadyCompleted)));
+
+        // A failed copy leaves the original running
+        assert!(manager.pop().is_none());
+        assert_eq!(manager.copies(&second), 1);
+        let lease = manager.copies.get(&second).unwrap()[0];
+        manager.fail_with_lease(second.to_uri(), "copy".to_string(), Some(lease)).unwrap();
+        assert_eq!(manager.copies(&second), 0);
+        assert!(manager.jobs_pending().contains_key(&second));
+        assert!(manager.pop().is_none());
+
+        // A batch holds at most one copy of each job
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_speculation(3);
+        assert_eq!(manager.pop_many(10).len(), 3);
+        for _ in 0..3 {
+            let mut copies: Vec<String> = manager.pop_many(10).iter().map(|job| job.to_uri()).collect();
+            copies.sort();
+            assert_eq!(copies, vec!["0", "1", "2"]);
+        }
+        assert!(manager.pop_many(10).is_empty());
+
+        // A heartbeat does not make a long running job look fresh
+        let mut manager = JobManager::new(&vec![2]).unwrap();
+        manager.with_speculation(1);
+        let first = manager.pop().unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        manager.pop().unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        manager.renew(first.to_uri(), first.lease).unwrap();
+        assert!(manager.pop().unwrap().eq(&first));
+    }
 }
\ No newline at end of file
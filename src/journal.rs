@@ -0,0 +1,78 @@
+use std::{fs::{File, OpenOptions}, io::{self, Read, Write}, path::PathBuf};
+
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    pub fn open(path: &PathBuf) -> Result<Self, io::Error> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+
+        // Drop a torn trailing entry so new entries start on a fresh line
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let valid = match contents.iter().rposition(|byte| *byte == b'\n') {
+            Some(position) => position + 1,
+            None => 0,
+        };
+        if valid < contents.len() {
+            file.set_len(valid as u64)?;
+        }
+        return Ok(Self {
+            file,
+        });
+    }
+
+    pub fn read(path: &PathBuf) -> Result<Vec<String>, io::Error> {
+        let mut contents = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut contents)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        // A crash can leave the last entry half written, so only complete lines count
+        let mut result: Vec<String> = contents.split('\n').map(|line| line.to_string()).collect();
+        result.pop();
+        return Ok(result);
+    }
+
+    pub fn append(&mut self, entry: &String) -> Result<(), io::Error> {
+        assert!(!entry.contains('\n'));
+        self.file.write_all(format!("{entry}\n").as_bytes())?;
+        return self.file.sync_data();
+    }
+
+    pub fn truncate(&mut self) -> Result<(), io::Error> {
+        self.file.set_len(0)?;
+        return self.file.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let path = std::env::temp_dir().join(format!("netspatch-journal-{}", std::process::id()));
+        assert!(Journal::read(&path).unwrap().is_empty());
+        {
+            let mut journal = Journal::open(&path).unwrap();
+            journal.append(&"1 pop 0".to_string()).unwrap();
+            journal.append(&"2 complete 0".to_string()).unwrap();
+        }
+
+        // A torn entry at the end is ignored
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"3 pop").unwrap();
+        assert_eq!(Journal::read(&path).unwrap(), vec!["1 pop 0", "2 complete 0"]);
+
+        let mut journal = Journal::open(&path).unwrap();
+        journal.append(&"3 complete 1".to_string()).unwrap();
+        assert_eq!(Journal::read(&path).unwrap(), vec!["1 pop 0", "2 complete 0", "3 complete 1"]);
+        journal.truncate().unwrap();
+        assert!(Journal::read(&path).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+pub fn escape(input: &str) -> String {
+    let mut result = String::with_capacity(input.len() + 2);
+    result.push('"');
+    for c in input.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    return result;
+}
+
+pub fn unescape(input: &str) -> Option<String> {
+    let inner = input.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '"' => result.push('"'),
+            '\\' => result.push('\\'),
+            '/' => result.push('/'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            'b' => result.push('\u{8}'),
+            'f' => result.push('\u{c}'),
+            'u' => {
+                let code: String = chars.by_ref().take(4).collect();
+                let value = u32::from_str_radix(&code, 16).ok()?;
+                result.push(char::from_u32(value)?);
+            }
+            _ => return None,
+        }
+    }
+    return Some(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("plain"), "\"plain\"");
+        assert_eq!(escape("a \"quote\"\r\n\\"), "\"a \\\"quote\\\"\\r\\n\\\\\"");
+        assert_eq!(escape("\u{1}"), "\"\\u0001\"");
+    }
+
+    #[test]
+    fn test_unescape() {
+        for input in ["plain", "a \"quote\"\r\n\\", "\u{1}\t"] {
+            assert_eq!(unescape(&escape(input)).unwrap(), input);
+        }
+        assert!(unescape("unquoted").is_none());
+        assert!(unescape("\"dangling\\\"").is_none());
+    }
+}
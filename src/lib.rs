@@ -1,4 +1,12 @@
+pub mod dashboard;
 pub mod http;
 pub mod job;
+pub mod journal;
+pub mod json;
+pub mod metrics;
+pub mod param;
+pub mod sink;
+pub mod status;
 pub mod client;
-pub mod server;
\ No newline at end of file
+pub mod server;
+pub mod worker;
\ No newline at end of file
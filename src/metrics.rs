@@ -0,0 +1,138 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::{job::JobManager, worker::WorkerRegistry};
+
+// Upper bounds of the latency buckets in seconds
+const BUCKETS: [f64; 10] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0];
+
+pub struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        return Self {
+            counts: [0; BUCKETS.len()],
+            count: 0,
+            sum: 0.0,
+        };
+    }
+
+    pub fn observe(&mut self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        for (bound, count) in BUCKETS.iter().zip(self.counts.iter_mut()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, name: &str, labels: &str, output: &mut String) {
+        for (bound, count) in BUCKETS.iter().zip(self.counts.iter()) {
+            output.push_str(&format!("{name}_bucket{{{labels},le=\"{bound}\"}} {count}\n"));
+        }
+        output.push_str(&format!("{name}_bucket{{{labels},le=\"+Inf\"}} {}\n", self.count));
+        output.push_str(&format!("{name}_sum{{{labels}}} {}\n", self.sum));
+        output.push_str(&format!("{name}_count{{{labels}}} {}\n", self.count));
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    latency: BTreeMap<&'static str, Histogram>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        return Self {
+            latency: BTreeMap::new(),
+        };
+    }
+
+    pub fn observe(&mut self, route: &'static str, latency: Duration) {
+        self.latency.entry(route).or_default().observe(latency);
+    }
+
+    pub fn render(&self, manager: &JobManager, workers: &WorkerRegistry, active: Duration) -> String {
+        let mut result = String::new();
+        let gauges = [
+            ("netspatch_points", "Points in the job space by state", vec![
+                ("total", manager.jobs_total()),
+                ("completed", manager.jobs_completed()),
+                ("remaining", manager.jobs_remaining()),
+            ]),
+            ("netspatch_jobs", "Dispatched jobs by state", vec![
+                ("pending", manager.pending_count()),
+                ("abandoned", manager.abandoned_count()),
+                ("failed", manager.failed_count()),
+            ]),
+        ];
+        for (name, help, values) in gauges {
+            result.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n"));
+            for (state, value) in values {
+                result.push_str(&format!("{name}{{state=\"{state}\"}} {value}\n"));
+            }
+        }
+
+        let counters = [
+            ("netspatch_dispatches_total", "Jobs handed to workers", manager.dispatch_count()),
+            ("netspatch_completions_total", "Jobs completed by workers", manager.completion_count()),
+            ("netspatch_abandons_total", "Jobs requeued after an expired lease or a failure", manager.abandon_count()),
+            ("netspatch_lease_expiries_total", "Leases that ran out before a result arrived", manager.expiry_count()),
+        ];
+        for (name, help, value) in counters {
+            result.push_str(&format!("# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n"));
+        }
+
+        // Workers count as connected while they keep contacting the server
+        let connected = workers.workers().iter().filter(|worker| worker.last_contact.elapsed().unwrap_or(Duration::ZERO) <= active).count();
+        result.push_str("# HELP netspatch_workers Workers seen by the server\n# TYPE netspatch_workers gauge\n");
+        result.push_str(&format!("netspatch_workers{{state=\"connected\"}} {connected}\n"));
+        result.push_str(&format!("netspatch_workers{{state=\"registered\"}} {}\n", workers.len()));
+
+        let name = "netspatch_request_duration_seconds";
+        result.push_str(&format!("# HELP {name} Time spent handling requests by route\n# TYPE {name} histogram\n"));
+        for (route, histogram) in &self.latency {
+            histogram.render(name, &format!("route=\"{route}\""), &mut result);
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let dimensions = vec![4];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        let job = manager.pop().unwrap();
+        manager.complete(job.to_uri()).unwrap();
+        let mut workers = WorkerRegistry::new();
+        workers.contact(&"worker".to_string());
+
+        let mut metrics = Metrics::new();
+        metrics.observe("dispatch", Duration::from_millis(2));
+        metrics.observe("dispatch", Duration::from_secs(2));
+        let output = metrics.render(&manager, &workers, Duration::from_secs(60));
+        assert!(output.contains("netspatch_points{state=\"completed\"} 1\n"));
+        assert!(output.contains("netspatch_dispatches_total 1\n"));
+        assert!(output.contains("netspatch_completions_total 1\n"));
+        assert!(output.contains("netspatch_workers{state=\"connected\"} 1\n"));
+        assert!(output.contains("netspatch_request_duration_seconds_bucket{route=\"dispatch\",le=\"0.001\"} 0\n"));
+        assert!(output.contains("netspatch_request_duration_seconds_bucket{route=\"dispatch\",le=\"0.0025\"} 1\n"));
+        assert!(output.contains("netspatch_request_duration_seconds_bucket{route=\"dispatch\",le=\"+Inf\"} 2\n"));
+        assert!(output.contains("netspatch_request_duration_seconds_count{route=\"dispatch\"} 2\n"));
+    }
+}
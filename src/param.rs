@@ -0,0 +1,269 @@
+use crate::job::Error;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Value {
+    pub fn parse(input: &str) -> Self {
+        // Quoted items are text even when they look like numbers
+        if let Some(text) = input.strip_prefix('"').and_then(|rem| rem.strip_suffix('"')) {
+            return Self::Text(text.to_string());
+        }
+        if let Ok(value) = input.parse::<i64>() {
+            return Self::Integer(value);
+        }
+        if let Ok(value) = input.parse::<f64>() {
+            return Self::Float(value);
+        }
+        return Self::Text(input.to_string());
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        return match self {
+            Self::Integer(value) => Some(*value),
+            _ => None,
+        };
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        return match self {
+            Self::Integer(value) => Some(*value as f64),
+            Self::Float(value) => Some(*value),
+            Self::Text(_) => None,
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            Self::Text(value) => Some(value),
+            _ => None,
+        };
+    }
+
+    pub fn to_string(&self) -> String {
+        return match self {
+            Self::Integer(value) => value.to_string(),
+            // Debug keeps the decimal point so whole floats do not come back as integers
+            Self::Float(value) => format!("{value:?}"),
+            Self::Text(value) => match Self::parse(value) {
+                Self::Text(text) if text.eq(value) => value.clone(),
+                _ => format!("\"{value}\""),
+            },
+        };
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Values {
+    Index,
+    List(Vec<Value>),
+    Range {
+        start: i64,
+        step: i64,
+    },
+    Linspace {
+        start: f64,
+        stop: f64,
+    },
+    Logspace {
+        start: f64,
+        stop: f64,
+    },
+}
+
+impl Values {
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let (kind, rem) = input.split_once(':').unwrap_or((input, ""));
+        return match kind {
+            "index" => Ok(Self::Index),
+            "list" => {
+                let mut values = Vec::new();
+                for item in rem.split(',') {
+                    values.push(Value::parse(item));
+                }
+                Ok(Self::List(values))
+            }
+            "range" => {
+                let (start, step) = Self::parse_pair::<i64>(rem)?;
+                Ok(Self::Range { start, step })
+            }
+            "linspace" => {
+                let (start, stop) = Self::parse_pair::<f64>(rem)?;
+                Ok(Self::Linspace { start, stop })
+            }
+            "logspace" => {
+                let (start, stop) = Self::parse_pair::<f64>(rem)?;
+                Ok(Self::Logspace { start, stop })
+            }
+            &_ => Err(Error::UnexpectedString),
+        };
+    }
+
+    fn parse_pair<T: std::str::FromStr>(input: &str) -> Result<(T, T), Error> {
+        let (first_str, second_str) = match input.split_once(':') {
+            Some(value) => value,
+            None => return Err(Error::UnexpectedString),
+        };
+        let first = match first_str.parse::<T>() {
+            Ok(value) => value,
+            Err(_) => return Err(Error::UnexpectedString),
+        };
+        let second = match second_str.parse::<T>() {
+            Ok(value) => value,
+            Err(_) => return Err(Error::UnexpectedString),
+        };
+        return Ok((first, second));
+    }
+
+    pub fn value(&self, index: usize, span: usize) -> Option<Value> {
+        if index >= span {
+            return None;
+        }
+        // Evenly spaced points include both endpoints
+        let fraction = if span > 1 {
+            index as f64 / (span - 1) as f64
+        } else {
+            0.0
+        };
+        return match self {
+            Self::Index => Some(Value::Integer(i64::try_from(index).ok()?)),
+            Self::List(values) => values.get(index).cloned(),
+            // Values past the integer range are reported as missing rather than wrapping
+            Self::Range { start, step } => {
+                let offset = step.checked_mul(i64::try_from(index).ok()?)?;
+                Some(Value::Integer(start.checked_add(offset)?))
+            }
+            Self::Linspace { start, stop } => Some(Value::Float(start + (stop - start) * fraction)),
+            Self::Logspace { start, stop } => Some(Value::Float(10_f64.powf(start + (stop - start) * fraction))),
+        };
+    }
+
+    pub fn to_string(&self) -> String {
+        return match self {
+            Self::Index => "index".to_string(),
+            Self::List(values) => {
+                let items: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                format!("list:{}", items.join(","))
+            }
+            Self::Range { start, step } => format!("range:{start}:{step}"),
+            Self::Linspace { start, stop } => format!("linspace:{start}:{stop}"),
+            Self::Logspace { start, stop } => format!("logspace:{start}:{stop}"),
+        };
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub values: Values,
+}
+
+impl Parameter {
+    pub fn new(name: &str, values: Values) -> Result<Self, Error> {
+        if name.len() == 0 || name.contains(|c: char| c == '=' || c.is_whitespace()) {
+            return Err(Error::UnexpectedString);
+        }
+        // List items travel comma separated on a single row
+        if let Values::List(items) = &values {
+            for item in items {
+                if let Value::Text(text) = item {
+                    if text.contains([',', '\r', '\n']) {
+                        return Err(Error::UnexpectedString);
+                    }
+                }
+            }
+        }
+        return Ok(Self {
+            name: name.to_string(),
+            values,
+        });
+    }
+
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let (name, spec) = match input.split_once('=') {
+            Some(value) => value,
+            None => (input, "index"),
+        };
+        return Self::new(name, Values::parse(spec)?);
+    }
+
+    pub fn value(&self, index: usize, span: usize) -> Option<Value> {
+        return self.values.value(index, span);
+    }
+
+    pub fn to_string(&self) -> String {
+        return format!("{}={}", self.name, self.values.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::{Job, JobManager};
+
+    #[test]
+    fn test_parameter_values() {
+        let index = Parameter::parse("seed").unwrap();
+        assert_eq!(index.value(3, 4), Some(Value::Integer(3)));
+        assert_eq!(index.value(4, 4), None);
+
+        let list = Parameter::parse("model=list:resnet,vgg,7,0.5").unwrap();
+        assert_eq!(list.value(0, 4).unwrap().as_str(), Some("resnet"));
+        assert_eq!(list.value(2, 4), Some(Value::Integer(7)));
+        assert_eq!(list.value(3, 4), Some(Value::Float(0.5)));
+
+        let range = Parameter::parse("batch=range:16:8").unwrap();
+        assert_eq!(range.value(2, 4).unwrap().as_i64(), Some(32));
+        let overflow = Parameter::parse(&format!("big=range:{}:1", i64::MAX - 1)).unwrap();
+        assert_eq!(overflow.value(1, 3).unwrap().as_i64(), Some(i64::MAX));
+        assert_eq!(overflow.value(2, 3), None);
+
+        let linspace = Parameter::parse("x=linspace:0:1").unwrap();
+        assert_eq!(linspace.value(0, 5).unwrap().as_f64(), Some(0.0));
+        assert_eq!(linspace.value(2, 5).unwrap().as_f64(), Some(0.5));
+        assert_eq!(linspace.value(4, 5).unwrap().as_f64(), Some(1.0));
+
+        let logspace = Parameter::parse("learning_rate=logspace:-3:0").unwrap();
+        assert_eq!(logspace.value(0, 4).unwrap().as_f64(), Some(0.001));
+        assert_eq!(logspace.value(3, 4).unwrap().as_f64(), Some(1.0));
+    }
+
+    #[test]
+    fn test_parameter_round_trip() {
+        for input in ["seed=index", "model=list:resnet,vgg", "batch=range:16:-8", "x=linspace:-1.5:2", "lr=logspace:-4:-1"] {
+            let parameter = Parameter::parse(input).unwrap();
+            assert_eq!(parameter.to_string(), input);
+            assert_eq!(Parameter::parse(&parameter.to_string()).unwrap(), parameter);
+        }
+        assert!(Parameter::parse("bad name=index").is_err());
+        assert!(Parameter::parse("x=linspace:0").is_err());
+        assert!(Parameter::parse("x=unknown").is_err());
+        assert!(Parameter::new("model", Values::List(vec![Value::Text("a,b".to_string())])).is_err());
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let values = vec![
+            Value::Integer(-7),
+            Value::Float(1.0),
+            Value::Float(1e-7),
+            Value::Float(f64::INFINITY),
+            Value::Text("inf".to_string()),
+            Value::Text("007".to_string()),
+            Value::Text("\"quoted\"".to_string()),
+            Value::Text("plain".to_string()),
+        ];
+        let dimensions = vec![values.len()];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        manager.with_parameters(vec![Parameter::new("v", Values::List(values.clone())).unwrap()]).unwrap();
+        for value in values {
+            let job = manager.pop().unwrap();
+            let parsed = Job::parse(&job.to_string()).unwrap();
+            assert_eq!(parsed.param("v"), Some(value));
+        }
+    }
+}
@@ -1,8 +1,119 @@
 use std::{
-    io::{prelude::*, BufReader}, net::{TcpListener, TcpStream}, sync::{Arc, Mutex, Barrier}, thread::{self, sleep, JoinHandle}, time::Duration
+    io::{prelude::*, BufReader}, panic::{self, AssertUnwindSafe}, net::{TcpListener, TcpStream}, sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc, Mutex, Barrier}, thread::{self, sleep, JoinHandle}, time::{Duration, Instant}
 };
 
-use crate::{client::Client, http::*, job::JobManager};
+use crate::{client::Client, dashboard, http::*, job::{Error, Job, JobManager, JobResult, LEASE_HEADER}, metrics::Metrics, sink::ResultSink, status::{Status, Throughput}, worker::{WorkerRegistry, WORKER_HEADER}};
+
+// Seconds an idle client should wait while other clients still hold jobs
+const RETRY_AFTER: u64 = 1;
+
+// Seconds of completions averaged into the reported throughput
+const THROUGHPUT_WINDOW: u64 = 60;
+
+// Seconds since its last request that a worker still counts as connected
+const WORKER_TIMEOUT: u64 = 60;
+
+// Connections handled at once unless configured otherwise
+const DEFAULT_THREADS: usize = 8;
+
+// Seconds a client may stall a read or write before it is dropped
+const DEFAULT_TIMEOUT: u64 = 30;
+
+// Most jobs a worker may ask for in one request
+const DEFAULT_MAX_BATCH: usize = 1024;
+
+// Seconds an idle persistent connection is kept open
+const DEFAULT_KEEP_ALIVE: u64 = 5;
+
+// How often an idle connection checks whether it should give up its thread
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+// State shared by every connection
+struct Context {
+    manager: Arc<Mutex<JobManager>>,
+    sink: Mutex<Box<dyn ResultSink>>,
+    workers: Arc<Mutex<WorkerRegistry>>,
+    throughput: Mutex<Throughput>,
+    metrics: Mutex<Metrics>,
+    options: ServerOptions,
+    shutdown: Arc<Mutex<bool>>,
+    queued: AtomicUsize,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    threads: usize,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    keep_alive: Option<Duration>,
+    max_batch: usize,
+    limits: RequestLimits,
+}
+
+impl ServerOptions {
+    pub fn new() -> Self {
+        return Self {
+            threads: DEFAULT_THREADS,
+            read_timeout: Some(Duration::new(DEFAULT_TIMEOUT, 0)),
+            write_timeout: Some(Duration::new(DEFAULT_TIMEOUT, 0)),
+            keep_alive: Some(Duration::new(DEFAULT_KEEP_ALIVE, 0)),
+            max_batch: DEFAULT_MAX_BATCH,
+            limits: RequestLimits::default(),
+        };
+    }
+
+    pub fn with_threads(&mut self, threads: usize) -> &mut Self {
+        assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_read_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        assert!(timeout != Some(Duration::ZERO));
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn with_write_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        assert!(timeout != Some(Duration::ZERO));
+        self.write_timeout = timeout;
+        self
+    }
+
+    // None closes every connection after a single request
+    pub fn with_keep_alive(&mut self, idle: Option<Duration>) -> &mut Self {
+        assert!(idle != Some(Duration::ZERO));
+        self.keep_alive = idle;
+        self
+    }
+
+    pub fn with_max_batch(&mut self, count: usize) -> &mut Self {
+        assert!(count > 0);
+        self.max_batch = count;
+        self
+    }
+
+    pub fn with_max_headers(&mut self, count: usize) -> &mut Self {
+        self.limits.max_headers = count;
+        self
+    }
+
+    pub fn with_max_header_size(&mut self, size: usize) -> &mut Self {
+        self.limits.max_header_size = size;
+        self
+    }
+
+    pub fn with_max_body_size(&mut self, size: usize) -> &mut Self {
+        self.limits.max_body_size = size;
+        self
+    }
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
 
 pub struct Server {
     host: String,
@@ -13,12 +124,28 @@ pub struct Server {
 }
 
 impl Server {
-    pub fn start(host: &String, port: u32, stack: Arc<Mutex<JobManager>>, fuse: Duration) -> Result<Arc<Self>, std::io::Error> {
+    pub fn start(host: &String, port: u32, stack: Arc<Mutex<JobManager>>, fuse: Duration, sink: Box<dyn ResultSink>) -> Result<Arc<Self>, std::io::Error> {
+        return Self::start_with_options(host, port, stack, fuse, sink, &ServerOptions::new());
+    }
+
+    pub fn start_with_options(host: &String, port: u32, stack: Arc<Mutex<JobManager>>, fuse: Duration, sink: Box<dyn ResultSink>, options: &ServerOptions) -> Result<Arc<Self>, std::io::Error> {
         let addr = format!("{}:{}", host, port);
         let listener = TcpListener::bind(addr)?;
         let shutdown = Arc::new(Mutex::new(false));
         let thread_shutdown = shutdown.clone();
         let watchdog_stack = stack.clone();
+        let workers = Arc::new(Mutex::new(WorkerRegistry::new()));
+        let watchdog_workers = workers.clone();
+        let context = Arc::new(Context {
+            manager: stack,
+            sink: Mutex::new(sink),
+            workers,
+            throughput: Mutex::new(Throughput::new(Duration::new(THROUGHPUT_WINDOW, 0))),
+            metrics: Mutex::new(Metrics::new()),
+            options: options.clone(),
+            shutdown: shutdown.clone(),
+            queued: AtomicUsize::new(0),
+        });
 
         // Create the run mutex and hold it until the server has started
         let run_mutex = Arc::new(Mutex::new(false));
@@ -26,19 +153,56 @@ impl Server {
         let barrier = Arc::new(Barrier::new(2));
         let thread_barrier = barrier.clone();
 
+        // Hand connections to a fixed pool so one slow client cannot stall the rest
+        let threads = options.threads;
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(threads);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut pool = Vec::with_capacity(threads);
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            let context = context.clone();
+            pool.push(thread::spawn(move || {
+                loop {
+                    let stream = receiver.lock().unwrap().recv();
+                    match stream {
+                        Ok(stream) => {
+                            context.queued.fetch_sub(1, Ordering::SeqCst);
+
+                            // A panic ends the connection but never the thread that serves it
+                            let result = panic::catch_unwind(AssertUnwindSafe(|| handle_connection(stream, &context)));
+                            if result.is_err() {
+                                eprintln!("Connection handler panicked");
+                            }
+                        }
+                        Err(_) => break,
+                    }
+                }
+            }));
+        }
+
         // Start the server thread
         let handle = thread::spawn(move || {
             let _hold = thread_mutex.lock().unwrap();
             thread_barrier.wait();
             for stream in listener.incoming() {
                 if stream.is_ok() {
-                    handle_connection(stream.unwrap(), stack.clone());
+                    context.queued.fetch_add(1, Ordering::SeqCst);
+                    if sender.send(stream.unwrap()).is_err() {
+                        eprintln!("Connection pool stopped");
+                        break;
+                    }
                 }
                 let lock = thread_shutdown.lock().unwrap();
                 if *lock {
                     break;
                 }
             }
+
+            // Let the pool finish the connections it already accepted
+            drop(sender);
+            for thread in pool {
+                thread.join().expect("Connection thread panicked");
+            }
         });
 
         let result = Arc::new(Self {
@@ -55,7 +219,20 @@ impl Server {
             loop {
                 let mut shutdown = false;
                 {
-                    let check = watchdog_stack.lock().unwrap();
+                    let mut check = watchdog_stack.lock().unwrap();
+                    let expired = check.expire();
+                    let failed = check.jobs_failed();
+                    for job in expired {
+                        watchdog_workers.lock().unwrap().release(&job);
+                        if failed.contains(&job) {
+                            eprintln!("Job {} failed after {} attempt(s)", job.to_uri(), check.attempts(&job));
+                        } else {
+                            eprintln!("Lease expired for job {}", job.to_uri());
+                        }
+                    }
+                    if let Err(err) = check.checkpoint_if_due() {
+                        eprintln!("Could not write checkpoint: {}", err);
+                    }
                     if check.is_finished() {
                         shutdown = true;
                     }
@@ -66,6 +243,9 @@ impl Server {
                     sleep(Duration::new(1, 0));
                 }
             }
+            if let Err(err) = watchdog_stack.lock().unwrap().checkpoint() {
+                eprintln!("Could not write checkpoint: {}", err);
+            }
             sleep(fuse);
             watchdog_server.stop().expect("Could not signal server stop");
         });
@@ -77,12 +257,11 @@ impl Server {
 
     pub fn stop(&self) -> Result<(), std::io::Error> {
         let mut client = Client::new(self.host.clone(), self.port);
-        {
-            let mut lock = self.shutdown.lock().unwrap();
-            *lock = true;
-            let request = HTTPRequest::new(crate::http::HTTPMethod::GET, "server".to_string());
-            client.send(request)?;
-        }
+        *self.shutdown.lock().unwrap() = true;
+
+        // Wake the listener so it sees the flag
+        let request = HTTPRequest::new(crate::http::HTTPMethod::GET, "server".to_string());
+        client.send(request)?;
         return Ok(());
     }
 
@@ -95,46 +274,443 @@ impl Server {
     }
 }
 
-fn handle_connection(mut stream: TcpStream, index: Arc<Mutex<JobManager>>) {
-    let buf_reader = BufReader::new(&stream);
+fn handle_connection(stream: TcpStream, context: &Context) {
+    // A misbehaving client must never take a pool thread down with it
+    if let Err(err) = stream.set_write_timeout(context.options.write_timeout) {
+        eprintln!("Could not configure connection: {}", err);
+        return;
+    }
+    let mut buf_reader = BufReader::new(&stream);
+    let mut first = true;
+    loop {
+        if !first && !wait_for_request(&mut buf_reader, &stream, context) {
+            return;
+        }
+        first = false;
+        if let Err(err) = stream.set_read_timeout(context.options.read_timeout) {
+            eprintln!("Could not configure connection: {}", err);
+            return;
+        }
+        if !handle_request(&mut buf_reader, &stream, context) {
+            return;
+        }
+    }
+}
+
+// Waits for the next request on a persistent connection
+fn wait_for_request(reader: &mut BufReader<&TcpStream>, stream: &TcpStream, context: &Context) -> bool {
+    let idle = match context.options.keep_alive {
+        Some(value) => value,
+        None => return false,
+    };
+    let started = Instant::now();
+    loop {
+        // Idle connections make way for queued ones and for shutdown
+        if context.queued.load(Ordering::SeqCst) > 0 || *context.shutdown.lock().unwrap() {
+            return false;
+        }
+        let remaining = match idle.checked_sub(started.elapsed()) {
+            Some(value) if !value.is_zero() => value,
+            _ => return false,
+        };
+        if stream.set_read_timeout(Some(remaining.min(IDLE_POLL))).is_err() {
+            return false;
+        }
+        match reader.fill_buf() {
+            Ok(buf) => return buf.len() > 0,
+            Err(err) if matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(_) => return false,
+        }
+    }
+}
 
-    let request = match HTTPRequest::read(buf_reader) {
+// Returns whether the connection stays open for another request
+fn handle_request(buf_reader: &mut BufReader<&TcpStream>, stream: &TcpStream, context: &Context) -> bool {
+    let mut request = match HTTPRequest::read_head(buf_reader, &context.options.limits) {
         Ok(value) => value,
         Err(code) => {
-            stream.write_all(HTTPResponse::new(code).as_string().as_bytes()).unwrap();
-            return;
+            respond(stream, &HTTPResponse::new(code), false);
+            return false;
+        }
+    };
+
+    // Clients that ask first wait for permission before sending the body
+    if request.expects_continue() {
+        if let Err(err) = (&*stream).write_all(b"HTTP/1.1 100 Continue\r\n\r\n") {
+            eprintln!("Could not send response: {}", err);
+            return false;
         }
+    }
+    if let Err(code) = request.read_content(buf_reader, &context.options.limits) {
+        respond(stream, &HTTPResponse::new(code), false);
+        return false;
+    }
+
+    let started = Instant::now();
+    let name = route(&request);
+    let keep_alive = context.options.keep_alive.is_some() && request.keeps_alive() && !*context.shutdown.lock().unwrap();
+    let lease = match request.header(LEASE_HEADER) {
+        Some(value) => match value.parse::<u64>() {
+            Ok(lease) => Some(lease),
+            Err(_) => {
+                return respond(stream, &HTTPResponse::new(HTTPResponseCode::BadRequest), keep_alive);
+            }
+        },
+        None => None,
     };
 
-    match request.method {
+    // Anonymous clients are served but not tracked
+    let worker = request.header(WORKER_HEADER).cloned();
+    if let Some(id) = &worker {
+        context.workers.lock().unwrap().contact(id);
+    }
+
+    let response = match request.method {
         HTTPMethod::GET => {
-            let mut response = HTTPResponse::new(HTTPResponseCode::OK);
-            if request.uri.len() > 0 {
-                response = HTTPResponse::new(HTTPResponseCode::NotFound);
-            } else {
-                let mut payload = index.lock().unwrap();
-                match (*payload).pop() {
-                    Some(job) => {
-                        response.content = job.to_string();
-                    }
-                    None => {
-                        response = HTTPResponse::new(HTTPResponseCode::NoContent);
-                    }
+            if request.uri == "dashboard" || (request.uri.len() == 0 && accepts_html(&request)) {
+                let manager = context.manager.lock().unwrap();
+                let status = Status::new(&manager, &mut context.throughput.lock().unwrap());
+                let mut response = HTTPResponse::new(HTTPResponseCode::OK);
+                response.headers.insert("Content-Type".to_string(), "text/html; charset=utf-8".to_string());
+                response.content = dashboard::render(&manager, &context.workers.lock().unwrap(), &status).into_bytes();
+                response
+            } else if request.uri.len() == 0 {
+                dispatch(context, 1, &worker)
+            } else if let Some(count_str) = request.uri.strip_prefix("batch/") {
+                match count_str.parse::<usize>() {
+                    Ok(count) if count > 0 && count <= context.options.max_batch => dispatch(context, count, &worker),
+                    _ => HTTPResponse::new(HTTPResponseCode::BadRequest),
                 }
+            } else if request.uri == "workers" {
+                let mut response = HTTPResponse::new(HTTPResponseCode::OK);
+                response.headers.insert("Content-Type".to_string(), "application/json".to_string());
+                response.content = context.workers.lock().unwrap().to_json().into_bytes();
+                response
+            } else if request.uri == "status" {
+                let manager = context.manager.lock().unwrap();
+                let status = Status::new(&manager, &mut context.throughput.lock().unwrap());
+                let mut response = HTTPResponse::new(HTTPResponseCode::OK);
+                response.headers.insert("Content-Type".to_string(), "application/json".to_string());
+                response.content = status.to_json().into_bytes();
+                response
+            } else if request.uri == "metrics" {
+                let manager = context.manager.lock().unwrap();
+                let workers = context.workers.lock().unwrap();
+                let mut response = HTTPResponse::new(HTTPResponseCode::OK);
+                response.headers.insert("Content-Type".to_string(), "text/plain; version=0.0.4".to_string());
+                response.content = context.metrics.lock().unwrap().render(&manager, &workers, Duration::new(WORKER_TIMEOUT, 0)).into_bytes();
+                response
+            } else {
+                HTTPResponse::new(HTTPResponseCode::NotFound)
             }
-            stream.write_all(response.as_string().as_bytes()).unwrap();
         }
         HTTPMethod::POST => {
-            let mut manager = index.lock().unwrap();
-            match manager.complete(request.uri) {
-                Ok(_) => { 
-                    println!("{}",request.body);
-                    stream.write_all(HTTPResponse::new(HTTPResponseCode::OK).as_string().as_bytes()).unwrap();
-                }
-                Err(_) => {
-                    stream.write_all(HTTPResponse::new(HTTPResponseCode::NotFound).as_string().as_bytes()).unwrap();
-                }
+            if let Some(uri) = request.uri.strip_prefix("fail/") {
+                // Reasons are meant to be read, so stray bytes are replaced
+                let reason = String::from_utf8_lossy(&request.body).to_string();
+                fail(context, uri.to_string(), reason, lease, &worker)
+            } else if let Some(uri) = request.uri.strip_prefix("renew/") {
+                renew(context, uri.to_string(), lease)
+            } else if request.uri == "batch" {
+                complete_batch(context, &request.body, &worker)
+            } else {
+                complete(context, request.uri, &request.body, lease, &worker)
+            }
+        }
+    };
+    let sent = respond(stream, &response, keep_alive);
+    context.metrics.lock().unwrap().observe(name, started.elapsed());
+    return sent && keep_alive;
+}
+
+fn respond(mut stream: &TcpStream, response: &HTTPResponse, keep_alive: bool) -> bool {
+    let mut response = response.clone();
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    response.headers.insert("Connection".to_string(), connection.to_string());
+    if response.status == HTTPResponseCode::MethodNotAllowed {
+        response.headers.insert("Allow".to_string(), "GET, POST".to_string());
+    }
+    if let Err(err) = stream.write_all(&response.as_bytes()) {
+        eprintln!("Could not send response: {}", err);
+        return false;
+    }
+    return true;
+}
+
+// Browsers asking for the root get the dashboard instead of a job
+fn accepts_html(request: &HTTPRequest) -> bool {
+    return match request.header("Accept") {
+        Some(value) => value.contains("text/html"),
+        None => false,
+    };
+}
+
+fn route(request: &HTTPRequest) -> &'static str {
+    return match request.method {
+        HTTPMethod::GET => match request.uri.as_str() {
+            "" if accepts_html(request) => "dashboard",
+            "" => "dispatch",
+            "dashboard" => "dashboard",
+            "workers" => "workers",
+            "status" => "status",
+            "metrics" => "metrics",
+            uri if uri.starts_with("batch/") => "dispatch_batch",
+            _ => "unknown",
+        },
+        HTTPMethod::POST => match request.uri.as_str() {
+            "batch" => "complete_batch",
+            uri if uri.starts_with("fail/") => "fail",
+            uri if uri.starts_with("renew/") => "renew",
+            _ => "complete",
+        },
+    };
+}
+
+fn dispatch(context: &Context, count: usize, worker: &Option<String>) -> HTTPResponse {
+    let mut manager = context.manager.lock().unwrap();
+
+    // Expire here rather than in pop so the registry learns which jobs were lost
+    for job in manager.expire() {
+        context.workers.lock().unwrap().release(&job);
+    }
+
+    // Jobs handed out without a journal entry could not be replayed after a restart
+    if let Some(err) = manager.journal_error() {
+        eprintln!("Not dispatching while the journal is unavailable: {}", err);
+        return HTTPResponse::new(HTTPResponseCode::InternalServerError);
+    }
+    let jobs = manager.pop_many(count);
+    if jobs.len() == 0 {
+        if let Some(err) = manager.journal_error() {
+            // Unjournaled jobs were put back and are dispatched once the journal recovers
+            eprintln!("Could not write to the journal: {}", err);
+            return HTTPResponse::new(HTTPResponseCode::InternalServerError);
+        }
+    }
+    if jobs.len() > 0 {
+        if let Some(id) = worker {
+            let mut workers = context.workers.lock().unwrap();
+            for job in &jobs {
+                workers.dispatch(id, job);
+            }
+        }
+        let mut response = HTTPResponse::new(HTTPResponseCode::OK);
+        let size: usize = jobs.iter().map(|job| job.size()).sum();
+        response.headers.insert("X-Job-Size".to_string(), size.to_string());
+        response.content = Job::list_to_string(&jobs).into_bytes();
+        return response;
+    } else if manager.is_finished() {
+        return HTTPResponse::new(HTTPResponseCode::NoContent);
+    } else {
+        // Jobs are still pending and may yet be abandoned
+        let mut response = HTTPResponse::new(HTTPResponseCode::ServiceUnavailable);
+        response.headers.insert("Retry-After".to_string(), RETRY_AFTER.to_string());
+        return response;
+    }
+}
+
+fn complete(context: &Context, uri: String, body: &Vec<u8>, lease: Option<u64>, worker: &Option<String>) -> HTTPResponse {
+    let mut manager = context.manager.lock().unwrap();
+    return HTTPResponse::new(store(&mut manager, context, uri, body, lease, worker));
+}
+
+fn complete_batch(context: &Context, body: &Vec<u8>, worker: &Option<String>) -> HTTPResponse {
+    let results = match JobResult::parse_list(body) {
+        Ok(value) => value,
+        Err(_) => return HTTPResponse::new(HTTPResponseCode::BadRequest),
+    };
+
+    // Report the outcome of every result on its own line
+    let mut manager = context.manager.lock().unwrap();
+    let mut response = HTTPResponse::new(HTTPResponseCode::OK);
+    for result in results {
+        let code = store(&mut manager, context, result.uri.clone(), &result.body, result.lease, worker);
+        response.content.extend_from_slice(format!("{} {}\r\n", result.uri, code.to_code()).as_bytes());
+    }
+    return response;
+}
+
+fn store(manager: &mut JobManager, context: &Context, uri: String, body: &Vec<u8>, lease: Option<u64>, worker: &Option<String>) -> HTTPResponseCode {
+    let job = match manager.from_uri(uri.clone()) {
+        Ok(value) => value,
+        Err(_) => return HTTPResponseCode::NotFound,
+    };
+    match manager.check_completion(&job, lease) {
+        Ok(_) => (),
+        Err(Error::AlreadyCompleted) | Err(Error::StaleLease) => return HTTPResponseCode::Conflict,
+        Err(_) => return HTTPResponseCode::NotFound,
+    }
+
+    // Only resolve the job once its result is safely stored
+    if let Err(err) = context.sink.lock().unwrap().write(&job, body) {
+        eprintln!("Could not store result for job {}: {}", job.to_uri(), err);
+        return HTTPResponseCode::InternalServerError;
+    }
+    let fresh = manager.is_outstanding(&job);
+    match manager.complete_with_lease(uri, lease) {
+        Ok(_) => (),
+        Err(Error::Journal(err)) => {
+            eprintln!("Could not write to the journal: {}", err);
+            return HTTPResponseCode::InternalServerError;
+        }
+        Err(err) => panic!("Checked job could not be completed: {:?}", err),
+    }
+    if fresh {
+        context.throughput.lock().unwrap().record(job.size());
+    }
+    let mut workers = context.workers.lock().unwrap();
+    match worker {
+        Some(id) => workers.complete(id, &job),
+        None => workers.release(&job),
+    }
+    return HTTPResponseCode::OK;
+}
+
+fn fail(context: &Context, uri: String, reason: String, lease: Option<u64>, worker: &Option<String>) -> HTTPResponse {
+    let mut manager = context.manager.lock().unwrap();
+    match manager.fail_with_lease(uri, reason.clone(), lease) {
+        Ok(job) => {
+            if let Some(id) = worker {
+                context.workers.lock().unwrap().fail(id, &job);
             }
+            eprintln!("Job {} reported failure: {}", job.to_uri(), reason);
+            return HTTPResponse::new(HTTPResponseCode::OK);
+        }
+        Err(Error::StaleLease) => {
+            return HTTPResponse::new(HTTPResponseCode::Conflict);
+        }
+        Err(Error::Journal(err)) => {
+            eprintln!("Could not write to the journal: {}", err);
+            return HTTPResponse::new(HTTPResponseCode::InternalServerError);
+        }
+        Err(_) => {
+            return HTTPResponse::new(HTTPResponseCode::NotFound);
+        }
+    }
+}
+
+fn renew(context: &Context, uri: String, lease: Option<u64>) -> HTTPResponse {
+    let mut manager = context.manager.lock().unwrap();
+    return match manager.renew(uri, lease) {
+        Ok(_) => HTTPResponse::new(HTTPResponseCode::OK),
+        Err(Error::StaleLease) => HTTPResponse::new(HTTPResponseCode::Conflict),
+        Err(_) => HTTPResponse::new(HTTPResponseCode::NotFound),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::GetJobResult, sink::StdoutSink};
+
+    #[test]
+    fn test_stalled_client() {
+        let host = "localhost".to_string();
+        let port = 7941;
+        let stack = Arc::new(Mutex::new(JobManager::new(&vec![4]).unwrap()));
+        let mut options = ServerOptions::new();
+        options.with_threads(2);
+        let server = Server::start_with_options(&host, port, stack, Duration::ZERO, Box::new(StdoutSink), &options).unwrap();
+
+        // A client that never finishes its request holds one thread
+        let mut stalled = TcpStream::connect(format!("{host}:{port}")).unwrap();
+        stalled.write_all(b"GET / HTTP/1.1\r\nHost: x\r\n").unwrap();
+        sleep(Duration::from_millis(100));
+
+        let started = Instant::now();
+        let mut client = Client::new(host.clone(), port);
+        assert!(client.query().success());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        drop(stalled);
+        server.stop().unwrap();
+        server.wait();
+    }
+
+    #[test]
+    fn test_wait_for_pending() {
+        let host = "localhost".to_string();
+        let port = 7942;
+        let stack = Arc::new(Mutex::new(JobManager::new(&vec![1]).unwrap()));
+        let server = Server::start(&host, port, stack, Duration::ZERO, Box::new(StdoutSink)).unwrap();
+        let mut first = Client::new(host.clone(), port);
+        assert!(first.query().success());
+
+        // Nothing is left to hand out, but the pending job may still come back
+        let mut second = Client::new(host.clone(), port);
+        assert_eq!(second.query(), GetJobResult::Wait(Duration::new(RETRY_AFTER, 0)));
+
+        assert_eq!(first.respond("done".to_string()).unwrap(), HTTPResponseCode::OK);
+        assert_eq!(second.query(), GetJobResult::NoJobsLeft);
+        server.wait();
+    }
+
+    #[test]
+    fn test_duplicate_response() {
+        let host = "localhost".to_string();
+        let port = 7943;
+        let stack = Arc::new(Mutex::new(JobManager::new(&vec![2]).unwrap()));
+        let server = Server::start(&host, port, stack, Duration::ZERO, Box::new(StdoutSink)).unwrap();
+        let mut client = Client::new(host.clone(), port);
+        assert!(client.query().success());
+        let job = client.job.clone();
+        assert_eq!(client.respond("first".to_string()).unwrap(), HTTPResponseCode::OK);
+
+        // The second result for the same job is rejected and the caller is told so
+        client.job = job;
+        assert_eq!(client.respond("second".to_string()).unwrap(), HTTPResponseCode::Conflict);
+        assert!(client.job.is_none());
+
+        assert!(client.query().success());
+        assert_eq!(client.fail("broken".to_string()).unwrap(), HTTPResponseCode::OK);
+        server.stop().unwrap();
+        server.wait();
+    }
+
+    struct CaptureSink(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl ResultSink for CaptureSink {
+        fn write(&mut self, _job: &Job, body: &Vec<u8>) -> Result<(), std::io::Error> {
+            self.0.lock().unwrap().push(body.clone());
+            return Ok(());
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_idle_reconnect() {
+        let host = "localhost".to_string();
+        let port = 7945;
+        let stack = Arc::new(Mutex::new(JobManager::new(&vec![2]).unwrap()));
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let mut options = ServerOptions::new();
+        options.with_keep_alive(Some(Duration::from_millis(200)));
+        let server = Server::start_with_options(&host, port, stack, Duration::ZERO, Box::new(CaptureSink(results.clone())), &options).unwrap();
+        let mut client = Client::new(host.clone(), port);
+        client.with_keep_alive(true);
+        assert!(client.query().success());
+
+        // The server drops the idle connection and the client quietly opens a new one
+        sleep(Duration::from_millis(500));
+        assert_eq!(client.respond("first".to_string()).unwrap(), HTTPResponseCode::OK);
+        assert!(client.query().success());
+        assert_eq!(*results.lock().unwrap(), vec![b"first".to_vec()]);
+
+        server.stop().unwrap();
+        server.wait();
+    }
+
+    #[test]
+    fn test_method_not_allowed() {
+        let host = "localhost".to_string();
+        let port = 7944;
+        let stack = Arc::new(Mutex::new(JobManager::new(&vec![1]).unwrap()));
+        let server = Server::start(&host, port, stack, Duration::ZERO, Box::new(StdoutSink)).unwrap();
+        let mut stream = TcpStream::connect(format!("{host}:{port}")).unwrap();
+        stream.write_all(b"PUT / HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+        let response = HTTPResponse::read(&mut BufReader::new(&stream)).ok().unwrap();
+        assert!(response.status == HTTPResponseCode::MethodNotAllowed);
+        assert_eq!(response.header("Allow").unwrap(), "GET, POST");
+        server.stop().unwrap();
+        server.wait();
+    }
+}
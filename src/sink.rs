@@ -0,0 +1,125 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, Write}, path::PathBuf};
+
+use crate::{job::Job, json};
+
+pub trait ResultSink: Send {
+    fn write(&mut self, job: &Job, body: &Vec<u8>) -> Result<(), io::Error>;
+}
+
+pub struct StdoutSink;
+
+impl ResultSink for StdoutSink {
+    fn write(&mut self, _job: &Job, body: &Vec<u8>) -> Result<(), io::Error> {
+        // Written as is, binary or not
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(body)?;
+        stdout.write_all(b"\n")?;
+        return stdout.flush();
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(input: &[u8]) -> String {
+    let mut result = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bits = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    return result;
+}
+
+pub struct JsonLinesSink<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        return Self {
+            writer,
+        };
+    }
+}
+
+impl JsonLinesSink<File> {
+    pub fn open(path: &PathBuf) -> Result<Self, io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        return Ok(Self::new(file));
+    }
+}
+
+impl<W: Write + Send> ResultSink for JsonLinesSink<W> {
+    fn write(&mut self, job: &Job, body: &Vec<u8>) -> Result<(), io::Error> {
+        // JSON strings hold text only, so anything else is base64 encoded
+        let line = match std::str::from_utf8(body) {
+            Ok(text) => format!("{{\"job\":{},\"result\":{}}}\n", json::escape(&job.to_uri()), json::escape(text)),
+            Err(_) => format!("{{\"job\":{},\"result_base64\":\"{}\"}}\n", json::escape(&job.to_uri()), base64(body)),
+        };
+        self.writer.write_all(line.as_bytes())?;
+        return self.writer.flush();
+    }
+}
+
+pub struct DirectorySink {
+    root: PathBuf,
+}
+
+impl DirectorySink {
+    pub fn new(root: &PathBuf) -> Result<Self, io::Error> {
+        fs::create_dir_all(root)?;
+        return Ok(Self {
+            root: root.clone(),
+        });
+    }
+
+    pub fn path(&self, job: &Job) -> PathBuf {
+        let mut result = self.root.clone();
+        for part in job.to_uri().split('/') {
+            result.push(part);
+        }
+        return result;
+    }
+}
+
+impl ResultSink for DirectorySink {
+    fn write(&mut self, job: &Job, body: &Vec<u8>) -> Result<(), io::Error> {
+        let path = self.path(job);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        return fs::write(path, body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_lines_sink() {
+        let dimensions = vec![2, 3];
+        let mut sink = JsonLinesSink::new(Vec::new());
+        sink.write(&Job::new(&vec![0, 1], &dimensions).unwrap(), &b"first\r\nline".to_vec()).unwrap();
+        sink.write(&Job::new(&vec![1, 2], &dimensions).unwrap(), &b"second".to_vec()).unwrap();
+        sink.write(&Job::new(&vec![1, 0], &dimensions).unwrap(), &vec![0xff, 0, 0x7f, 1]).unwrap();
+        let output = String::from_utf8(sink.writer).unwrap();
+        assert_eq!(output, "{\"job\":\"0/1\",\"result\":\"first\\r\\nline\"}\n{\"job\":\"1/2\",\"result\":\"second\"}\n{\"job\":\"1/0\",\"result_base64\":\"/wB/AQ==\"}\n");
+    }
+
+    #[test]
+    fn test_directory_sink() {
+        let root = std::env::temp_dir().join(format!("netspatch-sink-{}", std::process::id()));
+        let mut sink = DirectorySink::new(&root).unwrap();
+        let job = Job::new(&vec![1, 2], &vec![2, 3]).unwrap();
+        sink.write(&job, &vec![0xff, 0, b'\n']).unwrap();
+        assert_eq!(sink.path(&job), root.join("1").join("2"));
+        assert_eq!(fs::read(sink.path(&job)).unwrap(), vec![0xff, 0, b'\n']);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::VecDeque, time::{Duration, SystemTime}};
+
+use crate::job::JobManager;
+
+// Completed points per second over a moving window
+pub struct Throughput {
+    window: Duration,
+    started: SystemTime,
+    samples: VecDeque<(SystemTime, usize)>,
+}
+
+impl Throughput {
+    pub fn new(window: Duration) -> Self {
+        assert!(!window.is_zero());
+        return Self {
+            window,
+            started: SystemTime::now(),
+            samples: VecDeque::new(),
+        };
+    }
+
+    pub fn record(&mut self, count: usize) {
+        let now = SystemTime::now();
+        self.samples.push_back((now, count));
+        self.trim(now);
+    }
+
+    fn trim(&mut self, now: SystemTime) {
+        while let Some((time, _)) = self.samples.front() {
+            if now.duration_since(*time).unwrap_or(Duration::ZERO) <= self.window {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn rate(&mut self) -> f64 {
+        let now = SystemTime::now();
+        self.trim(now);
+
+        // A young run has not filled the window yet
+        let elapsed = now.duration_since(self.started).unwrap_or(Duration::ZERO);
+        let span = if elapsed < self.window { elapsed } else { self.window };
+        if span.is_zero() {
+            return 0.0;
+        }
+        let count: usize = self.samples.iter().map(|(_, count)| count).sum();
+        return count as f64 / span.as_secs_f64();
+    }
+}
+
+pub struct Status {
+    pub total: usize,
+    pub completed: usize,
+    pub remaining: usize,
+    pub pending: usize,
+    pub abandoned: usize,
+    pub failed: usize,
+    pub throughput: f64,
+    pub eta: Option<Duration>,
+}
+
+impl Status {
+    pub fn new(manager: &JobManager, throughput: &mut Throughput) -> Self {
+        let rate = throughput.rate();
+        let eta = if rate > 0.0 {
+            Some(Duration::from_secs_f64(manager.jobs_outstanding() as f64 / rate))
+        } else {
+            None
+        };
+        return Self {
+            total: manager.jobs_total(),
+            completed: manager.jobs_completed(),
+            remaining: manager.jobs_remaining(),
+            pending: manager.pending_count(),
+            abandoned: manager.abandoned_count(),
+            failed: manager.failed_count(),
+            throughput: rate,
+            eta,
+        };
+    }
+
+    pub fn to_json(&self) -> String {
+        // Totals count points while pending, abandoned and failed count jobs
+        let (eta_seconds, eta) = match self.eta {
+            Some(value) => {
+                let at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or(Duration::ZERO) + value;
+                (value.as_secs_f64().to_string(), at.as_secs_f64().to_string())
+            }
+            None => ("null".to_string(), "null".to_string()),
+        };
+        return format!(
+            "{{\"total\":{},\"completed\":{},\"remaining\":{},\"pending\":{},\"abandoned\":{},\"failed\":{},\"throughput\":{},\"eta_seconds\":{},\"eta\":{}}}",
+            self.total,
+            self.completed,
+            self.remaining,
+            self.pending,
+            self.abandoned,
+            self.failed,
+            self.throughput,
+            eta_seconds,
+            eta
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let dimensions = vec![10];
+        let mut manager = JobManager::new(&dimensions).unwrap();
+        let mut throughput = Throughput::new(Duration::from_secs(60));
+        let status = Status::new(&manager, &mut throughput);
+        assert!(status.eta.is_none());
+        assert!(status.to_json().ends_with("\"eta_seconds\":null,\"eta\":null}"));
+
+        let job = manager.pop().unwrap();
+        manager.pop().unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        manager.complete(job.to_uri()).unwrap();
+        throughput.record(job.size());
+        let status = Status::new(&manager, &mut throughput);
+        assert_eq!(status.total, 10);
+        assert_eq!(status.completed, 1);
+        assert_eq!(status.remaining, 8);
+        assert_eq!(status.pending, 1);
+        assert!(status.throughput > 0.0);
+        assert!(status.eta.is_some());
+        assert!(status.to_json().starts_with("{\"total\":10,\"completed\":1,\"remaining\":8,\"pending\":1,\"abandoned\":0,\"failed\":0,"));
+    }
+}
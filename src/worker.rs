@@ -0,0 +1,158 @@
+use std::{collections::HashMap, time::{Duration, SystemTime}};
+
+use crate::{job::Job, json};
+
+pub const WORKER_HEADER: &str = "X-Worker-Id";
+
+#[derive(Clone)]
+pub struct Worker {
+    pub id: String,
+    pub last_contact: SystemTime,
+    pub held: HashMap<Job, SystemTime>,
+    pub completed: usize,
+    pub failed: usize,
+    total_duration: Duration,
+}
+
+impl Worker {
+    pub fn new(id: &String) -> Self {
+        return Self {
+            id: id.clone(),
+            last_contact: SystemTime::now(),
+            held: HashMap::new(),
+            completed: 0,
+            failed: 0,
+            total_duration: Duration::ZERO,
+        };
+    }
+
+    pub fn mean_duration(&self) -> Option<Duration> {
+        if self.completed == 0 {
+            return None;
+        }
+        return Some(self.total_duration / self.completed as u32);
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut held: Vec<String> = self.held.keys().map(|job| json::escape(&job.to_uri())).collect();
+        held.sort();
+        let last_contact = self.last_contact.duration_since(SystemTime::UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        let mean_duration = match self.mean_duration() {
+            Some(value) => value.as_secs_f64().to_string(),
+            None => "null".to_string(),
+        };
+        return format!(
+            "{{\"id\":{},\"last_contact\":{},\"held\":[{}],\"completed\":{},\"failed\":{},\"mean_duration\":{}}}",
+            json::escape(&self.id),
+            last_contact.as_secs_f64(),
+            held.join(","),
+            self.completed,
+            self.failed,
+            mean_duration
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct WorkerRegistry {
+    workers: HashMap<String, Worker>,
+}
+
+impl WorkerRegistry {
+    pub fn new() -> Self {
+        return Self {
+            workers: HashMap::new(),
+        };
+    }
+
+    pub fn contact(&mut self, id: &String) -> &mut Worker {
+        let worker = self.workers.entry(id.clone()).or_insert_with(|| Worker::new(id));
+        worker.last_contact = SystemTime::now();
+        return worker;
+    }
+
+    pub fn dispatch(&mut self, id: &String, job: &Job) {
+        self.contact(id).held.insert(job.clone(), SystemTime::now());
+    }
+
+    pub fn complete(&mut self, id: &String, job: &Job) {
+        let worker = self.contact(id);
+        if let Some(since) = worker.held.remove(job) {
+            worker.total_duration += SystemTime::now().duration_since(since).unwrap_or(Duration::ZERO);
+        }
+        worker.completed += 1;
+
+        // Duplicates still running elsewhere are no longer needed
+        self.release(job);
+    }
+
+    pub fn fail(&mut self, id: &String, job: &Job) {
+        let worker = self.contact(id);
+        worker.held.remove(job);
+        worker.failed += 1;
+    }
+
+    pub fn release(&mut self, job: &Job) {
+        for worker in self.workers.values_mut() {
+            worker.held.remove(job);
+        }
+    }
+
+    pub fn get(&self, id: &String) -> Option<&Worker> {
+        return self.workers.get(id);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.workers.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.workers.is_empty();
+    }
+
+    pub fn workers(&self) -> Vec<Worker> {
+        let mut result: Vec<Worker> = self.workers.values().cloned().collect();
+        result.sort_by(|a, b| a.id.cmp(&b.id));
+        return result;
+    }
+
+    pub fn to_json(&self) -> String {
+        let workers: Vec<String> = self.workers().iter().map(|worker| worker.to_json()).collect();
+        return format!("[{}]", workers.join(","));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_registry() {
+        let dimensions = vec![4];
+        let first = Job::from_uri(&"0".to_string(), &dimensions).unwrap();
+        let second = Job::from_uri(&"1".to_string(), &dimensions).unwrap();
+        let alice = "alice".to_string();
+        let bob = "bob".to_string();
+
+        let mut registry = WorkerRegistry::new();
+        registry.dispatch(&alice, &first);
+        registry.dispatch(&bob, &first);
+        registry.dispatch(&bob, &second);
+        assert_eq!(registry.len(), 2);
+        assert!(registry.get(&alice).unwrap().mean_duration().is_none());
+
+        // Completing a job releases every copy of it
+        registry.complete(&alice, &first);
+        assert_eq!(registry.get(&alice).unwrap().completed, 1);
+        assert!(registry.get(&alice).unwrap().mean_duration().is_some());
+        assert_eq!(registry.get(&bob).unwrap().held.len(), 1);
+
+        registry.fail(&bob, &second);
+        assert_eq!(registry.get(&bob).unwrap().failed, 1);
+        assert!(registry.get(&bob).unwrap().held.is_empty());
+
+        let json = registry.to_json();
+        assert!(json.starts_with("[{\"id\":\"alice\""));
+        assert!(json.contains("\"held\":[],\"completed\":0,\"failed\":1,\"mean_duration\":null}]"));
+    }
+}